@@ -2,17 +2,26 @@ use support::{
 	decl_module, decl_storage, decl_event, ensure, StorageValue, StorageMap,
 	Parameter, traits::Currency
 };
-use sr_primitives::traits::{SimpleArithmetic, Bounded, Member};
+use sr_primitives::traits::{SimpleArithmetic, Bounded, Member, AccountIdConversion};
+use sr_primitives::ModuleId;
 use codec::{Encode, Decode};
 use runtime_io::blake2_128;
 use system::ensure_signed;
-use rstd::result;
+use rstd::{result, prelude::*};
 use crate::linked_item::{LinkedList, LinkedItem};
+use crate::nfts::{NFTS, OnNftTransfer};
+
+/// Equipped accessories are held by this module's account.
+const MODULE_ID: ModuleId = ModuleId(*b"kitties!");
 
 pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 	type KittyIndex: Parameter + Member + SimpleArithmetic + Bounded + Default + Copy;
 	type Currency: Currency<Self::AccountId>;
+	/// The token index of accessories.
+	type AccessoryIndex: Parameter + Member + Copy;
+	/// The NFT registry accessories are minted in.
+	type Accessories: NFTS<Self::AccountId, Self::AccessoryIndex>;
 }
 
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
@@ -35,7 +44,12 @@ decl_storage! {
 		/// Get kitty owner
 		pub KittyOwners get(kitty_owner): map T::KittyIndex => Option<T::AccountId>;
 		/// Get kitty price. None means not for sale.
-		pub KittyPrices get(kitty_price): map T::KittyIndex => Option<BalanceOf<T>>
+		pub KittyPrices get(kitty_price): map T::KittyIndex => Option<BalanceOf<T>>;
+
+		/// Accessories equipped on a kitty
+		pub KittyEquipment get(equipment_of): map T::KittyIndex => Vec<T::AccessoryIndex>;
+		/// The kitty an accessory is equipped on
+		pub EquippedOn get(equipped_on): map T::AccessoryIndex => Option<T::KittyIndex>;
 	}
 }
 
@@ -43,6 +57,7 @@ decl_event!(
 	pub enum Event<T> where
 		<T as system::Trait>::AccountId,
 		<T as Trait>::KittyIndex,
+		<T as Trait>::AccessoryIndex,
 		Balance = BalanceOf<T>,
 	{
 		/// A kitty is created. (owner, kitty_id)
@@ -53,6 +68,10 @@ decl_event!(
 		Ask(AccountId, KittyIndex, Option<Balance>),
 		/// A kitty is sold. (from, to, kitty_id, price)
 		Sold(AccountId, AccountId, KittyIndex, Balance),
+		/// An accessory is equipped. (owner, kitty_id, accessory)
+		Equipped(AccountId, KittyIndex, AccessoryIndex),
+		/// An accessory is unequipped. (owner, kitty_id, accessory)
+		Unequipped(AccountId, KittyIndex, AccessoryIndex),
 	}
 );
 
@@ -132,6 +151,36 @@ decl_module! {
 
 			Self::deposit_event(RawEvent::Sold(owner, sender, kitty_id, kitty_price));
 		}
+
+		/// Equip an owned accessory on an owned kitty. The accessory is held in escrow until unequipped.
+		pub fn equip(origin, kitty_id: T::KittyIndex, accessory: T::AccessoryIndex) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(<OwnedKitties<T>>::exists(&(sender.clone(), Some(kitty_id))), "Only owner can equip kitty");
+
+			// Fails unless the sender owns the accessory
+			T::Accessories::transfer_from(sender.clone(), Self::account_id(), accessory, Vec::new())?;
+
+			<KittyEquipment<T>>::mutate(kitty_id, |equipment| equipment.push(accessory));
+			<EquippedOn<T>>::insert(accessory, kitty_id);
+
+			Self::deposit_event(RawEvent::Equipped(sender, kitty_id, accessory));
+		}
+
+		/// Take an accessory off an owned kitty and return it to the sender.
+		pub fn unequip(origin, kitty_id: T::KittyIndex, accessory: T::AccessoryIndex) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(<OwnedKitties<T>>::exists(&(sender.clone(), Some(kitty_id))), "Only owner can unequip kitty");
+			ensure!(Self::equipped_on(accessory) == Some(kitty_id), "Accessory is not equipped on this kitty");
+
+			T::Accessories::transfer_from(Self::account_id(), sender.clone(), accessory, Vec::new())?;
+
+			<KittyEquipment<T>>::mutate(kitty_id, |equipment| equipment.retain(|item| *item != accessory));
+			<EquippedOn<T>>::remove(accessory);
+
+			Self::deposit_event(RawEvent::Unequipped(sender, kitty_id, accessory));
+		}
 	}
 }
 
@@ -140,6 +189,11 @@ fn combine_dna(dna1: u8, dna2: u8, selector: u8) -> u8 {
 }
 
 impl<T: Trait> Module<T> {
+	/// The account holding equipped accessories
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
 	fn random_value(sender: &T::AccountId) -> [u8; 16] {
 		let payload = (<system::Module<T>>::random_seed(), sender, <system::Module<T>>::extrinsic_index(), <system::Module<T>>::block_number());
 		payload.using_encoded(blake2_128)
@@ -195,6 +249,20 @@ impl<T: Trait> Module<T> {
 		Ok(kitty_id)
 	}
 
+	/// Move an owned kitty on behalf of another module, e.g. to escrow it.
+	/// Any asking price is cleared so an escrowed kitty can't be bought.
+	pub fn transfer_kitty(from: &T::AccountId, to: &T::AccountId, kitty_id: T::KittyIndex) -> result::Result<(), &'static str> {
+		ensure!(<OwnedKitties<T>>::exists(&(from.clone(), Some(kitty_id))), "Only owner can transfer kitty");
+
+		<KittyPrices<T>>::remove(kitty_id);
+		Self::do_transfer(from, to, kitty_id);
+
+		Self::deposit_event(RawEvent::Transferred(from.clone(), to.clone(), kitty_id));
+		Ok(())
+	}
+
+	/// Equipment stays escrowed against the kitty index, so it moves with the kitty
+	/// and the new owner can unequip it.
 	fn do_transfer(from: &T::AccountId, to: &T::AccountId, kitty_id: T::KittyIndex)  {
  		<OwnedKittiesList<T>>::remove(&from, kitty_id);
  		<OwnedKittiesList<T>>::append(&to, kitty_id);
@@ -202,85 +270,29 @@ impl<T: Trait> Module<T> {
  	}
 }
 
+/// Forget accessories burned while equipped, e.g. when they expire.
+impl<T: Trait> OnNftTransfer<T::AccountId, T::AccessoryIndex> for Module<T> {
+	fn on_nft_transfer(from: Option<&T::AccountId>, to: Option<&T::AccountId>, token_id: T::AccessoryIndex, _data: &[u8]) -> result::Result<(), &'static str> {
+		if to.is_none() && from == Some(&Self::account_id()) {
+			if let Some(kitty_id) = <EquippedOn<T>>::take(token_id) {
+				<KittyEquipment<T>>::mutate(kitty_id, |equipment| equipment.retain(|item| *item != token_id));
+			}
+		}
+		Ok(())
+	}
+}
+
 /// Tests for Kitties module
 #[cfg(test)]
 mod tests {
 	use super::*;
 
 	use runtime_io::with_externalities;
-	use primitives::{H256, Blake2Hasher};
-	use support::{impl_outer_origin, parameter_types};
-	use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
-	use sr_primitives::weights::Weight;
-	use sr_primitives::Perbill;
-
-	impl_outer_origin! {
-		pub enum Origin for Test {}
-	}
+	use support::{assert_ok, assert_noop};
+	use crate::mock::*;
 
-	// For testing the module, we construct most of a mock runtime. This means
-	// first constructing a configuration type (`Test`) which `impl`s each of the
-	// configuration traits of modules we want to use.
-	#[derive(Clone, Eq, PartialEq, Debug)]
-	pub struct Test;
-	parameter_types! {
-		pub const BlockHashCount: u64 = 250;
-		pub const MaximumBlockWeight: Weight = 1024;
-		pub const MaximumBlockLength: u32 = 2 * 1024;
-		pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-	}
-	impl system::Trait for Test {
-		type Origin = Origin;
-		type Call = ();
-		type Index = u64;
-		type BlockNumber = u64;
-		type Hash = H256;
-		type Hashing = BlakeTwo256;
-		type AccountId = u64;
-		type Lookup = IdentityLookup<Self::AccountId>;
-		type Header = Header;
-		type WeightMultiplierUpdate = ();
-		type Event = ();
-		type BlockHashCount = BlockHashCount;
-		type MaximumBlockWeight = MaximumBlockWeight;
-		type MaximumBlockLength = MaximumBlockLength;
-		type AvailableBlockRatio = AvailableBlockRatio;
-		type Version = ();
-	}
-	parameter_types! {
-		pub const ExistentialDeposit: u64 = 0;
-		pub const TransferFee: u64 = 0;
-		pub const CreationFee: u64 = 0;
-		pub const TransactionBaseFee: u64 = 0;
-		pub const TransactionByteFee: u64 = 0;
-	}
-	impl balances::Trait for Test {
-		type Balance = u64;
-		type OnFreeBalanceZero = ();
-		type OnNewAccount = ();
-		type Event = ();
-		type TransactionPayment = ();
-		type TransferPayment = ();
-		type DustRemoval = ();
-		type ExistentialDeposit = ExistentialDeposit;
-		type TransferFee = TransferFee;
-		type CreationFee = CreationFee;
-		type TransactionBaseFee = TransactionBaseFee;
-		type TransactionByteFee = TransactionByteFee;
-		type WeightToFee = ();
-	}
-	impl Trait for Test {
-		type KittyIndex = u32;
-		type Currency = balances::Module<Test>;
-		type Event = ();
-	}
 	type OwnedKittiesTest = OwnedKitties<Test>;
-
-	// This function basically just builds a genesis storage key/value store according to
-	// our desired mockup.
-	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
-		system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
-	}
+	type KittiesModule = Module<Test>;
 
 	#[test]
 	fn owned_kitties_can_append_values() {
@@ -394,4 +406,49 @@ mod tests {
 			assert_eq!(OwnedKittiesTest::get(&(0, Some(2))), None);
 		});
 	}
+
+	#[test]
+	fn equipment_travels_with_kitty() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			assert_ok!(NFTs::set_minter(Origin::ROOT, alice, true));
+			assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			assert_ok!(<NFTs as NFTS<_, _>>::transfer_from(alice, bob, 1, vec![]));
+			assert_ok!(KittiesModule::create(Origin::signed(alice)));
+
+			assert_noop!(KittiesModule::equip(Origin::signed(bob), 0, 1), "Only owner can equip kitty");
+			assert_noop!(KittiesModule::equip(Origin::signed(alice), 0, 1), "'from' account does not own this token");
+			assert_ok!(KittiesModule::equip(Origin::signed(alice), 0, 0));
+			assert_eq!(KittiesModule::equipment_of(0), vec![0]);
+			assert_eq!(KittiesModule::equipped_on(0), Some(0));
+			assert_eq!(NFTs::owner_of(0), Some(KittiesModule::account_id()));
+
+			assert_ok!(KittiesModule::transfer(Origin::signed(alice), bob, 0));
+			assert_noop!(KittiesModule::unequip(Origin::signed(alice), 0, 0), "Only owner can unequip kitty");
+			assert_ok!(KittiesModule::unequip(Origin::signed(bob), 0, 0));
+			assert_eq!(KittiesModule::equipment_of(0), Vec::<u128>::new());
+			assert_eq!(NFTs::owner_of(0), Some(bob));
+		});
+	}
+
+	#[test]
+	fn burned_accessory_is_unequipped() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			assert_ok!(NFTs::set_minter(Origin::ROOT, alice, true));
+			assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			assert_ok!(KittiesModule::create(Origin::signed(alice)));
+			assert_ok!(KittiesModule::equip(Origin::signed(alice), 0, 0));
+			assert_ok!(KittiesModule::equip(Origin::signed(alice), 0, 1));
+
+			// e.g. swept once it expires
+			assert_ok!(<NFTs as NFTS<_, _>>::_burn(0));
+			assert_eq!(KittiesModule::equipped_on(0), None);
+			assert_eq!(KittiesModule::equipment_of(0), vec![1]);
+			assert_noop!(KittiesModule::unequip(Origin::signed(alice), 0, 0), "Accessory is not equipped on this kitty");
+		});
+	}
 }
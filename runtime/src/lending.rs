@@ -0,0 +1,275 @@
+use support::{
+	decl_module, decl_storage, decl_event, ensure, StorageValue, StorageMap,
+	traits::{Currency, ReservableCurrency},
+};
+use sr_primitives::traits::{AccountIdConversion, Zero};
+use sr_primitives::ModuleId;
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use rstd::prelude::*;
+use crate::nfts::{self, NFTS};
+
+/// Collateral is held by this module's account while a loan is open.
+const MODULE_ID: ModuleId = ModuleId(*b"nftlends");
+
+pub trait Trait: system::Trait + nfts::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	type Currency: ReservableCurrency<Self::AccountId>;
+}
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+pub type LoanIndex = u32;
+
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct Loan<AccountId, NFTIndex, Balance, BlockNumber> {
+	pub borrower: AccountId,
+	/// The escrowed collateral.
+	pub token_id: NFTIndex,
+	/// Paid to the borrower when the loan is funded.
+	pub amount: Balance,
+	/// Paid to the lender to get the collateral back.
+	pub repayment: Balance,
+	/// The loan must be repaid before this block, after which the lender can claim the collateral.
+	pub deadline: BlockNumber,
+	/// None until the loan is funded.
+	pub lender: Option<AccountId>,
+}
+
+type LoanOf<T> = Loan<
+	<T as system::Trait>::AccountId,
+	<T as nfts::Trait>::NFTIndex,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+>;
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Lending {
+		/// The number of loans requested so far. i.e. the next loan index
+		pub LoanCount get(loan_count): LoanIndex;
+		/// Open loans, funded or not
+		pub Loans get(loan): map LoanIndex => Option<LoanOf<T>>;
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where
+		<T as system::Trait>::AccountId,
+		<T as nfts::Trait>::NFTIndex,
+		<T as system::Trait>::BlockNumber,
+		Balance = BalanceOf<T>,
+	{
+		/// A loan is requested against a token. (borrower, loan_index, token_id, amount, repayment, deadline)
+		Requested(AccountId, LoanIndex, NFTIndex, Balance, Balance, BlockNumber),
+		/// An unfunded loan is cancelled. (loan_index)
+		Cancelled(LoanIndex),
+		/// A loan is funded. (lender, loan_index)
+		Funded(AccountId, LoanIndex),
+		/// A loan is repaid and the collateral returned. (loan_index)
+		Repaid(LoanIndex),
+		/// An overdue loan's collateral is claimed by the lender. (loan_index)
+		Liquidated(LoanIndex),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Escrow an owned token and ask to borrow `amount`, to be repaid as `repayment` before `deadline`.
+		pub fn request(origin, token_id: T::NFTIndex, amount: BalanceOf<T>, repayment: BalanceOf<T>, deadline: T::BlockNumber) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(deadline > <system::Module<T>>::block_number(), "Deadline has passed");
+			ensure!(repayment >= amount, "Repayment is less than amount");
+			ensure!(<nfts::Module<T>>::expires_at(token_id).map_or(true, |expires_at| expires_at > deadline),
+				"Collateral expires before the deadline");
+
+			let index = Self::loan_count();
+			let next = index.checked_add(1).ok_or("Loan count overflow")?;
+
+			// Fails unless the sender owns the token
+			<nfts::Module<T>>::transfer_from(sender.clone(), Self::account_id(), token_id, Vec::new())?;
+
+			<LoanCount>::put(next);
+			<Loans<T>>::insert(index, Loan {
+				borrower: sender.clone(),
+				token_id,
+				amount,
+				repayment,
+				deadline,
+				lender: None,
+			});
+
+			Self::deposit_event(RawEvent::Requested(sender, index, token_id, amount, repayment, deadline));
+		}
+
+		/// Withdraw an unfunded loan request and get the collateral back.
+		pub fn cancel(origin, index: LoanIndex) {
+			let sender = ensure_signed(origin)?;
+
+			let loan = Self::loan(index).ok_or("Loan does not exist")?;
+			ensure!(loan.borrower == sender, "Only borrower can cancel");
+			ensure!(loan.lender.is_none(), "Loan is already funded");
+
+			<nfts::Module<T>>::transfer_from(Self::account_id(), sender, loan.token_id, Vec::new())?;
+			<Loans<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::Cancelled(index));
+		}
+
+		/// Pay the requested amount to the borrower and become the loan's lender.
+		pub fn fund(origin, index: LoanIndex) {
+			let sender = ensure_signed(origin)?;
+
+			let mut loan = Self::loan(index).ok_or("Loan does not exist")?;
+			ensure!(loan.lender.is_none(), "Loan is already funded");
+			ensure!(<system::Module<T>>::block_number() < loan.deadline, "Deadline has passed");
+
+			<T as Trait>::Currency::transfer(&sender, &loan.borrower, loan.amount)?;
+
+			loan.lender = Some(sender.clone());
+			<Loans<T>>::insert(index, loan);
+
+			Self::deposit_event(RawEvent::Funded(sender, index));
+		}
+
+		/// Pay the repayment to the lender before the deadline and get the collateral back.
+		pub fn repay(origin, index: LoanIndex) {
+			let sender = ensure_signed(origin)?;
+
+			let loan = Self::loan(index).ok_or("Loan does not exist")?;
+			ensure!(loan.borrower == sender, "Only borrower can repay");
+			let lender = loan.lender.ok_or("Loan is not funded")?;
+			ensure!(<system::Module<T>>::block_number() < loan.deadline, "Loan is overdue");
+
+			// Hold the repayment first, so nothing below can fail once the collateral has moved
+			ensure!(<T as Trait>::Currency::free_balance(&sender) >= loan.repayment, "Not enough balance to repay");
+			ensure!(!<T as Trait>::Currency::total_balance(&lender).is_zero(), "Lender account does not exist");
+			<T as Trait>::Currency::reserve(&sender, loan.repayment)?;
+			if let Err(e) = <nfts::Module<T>>::transfer_from(Self::account_id(), sender.clone(), loan.token_id, Vec::new()) {
+				<T as Trait>::Currency::unreserve(&sender, loan.repayment);
+				return Err(e);
+			}
+			<Loans<T>>::remove(index);
+			let _ = <T as Trait>::Currency::repatriate_reserved(&sender, &lender, loan.repayment);
+
+			Self::deposit_event(RawEvent::Repaid(index));
+		}
+
+		/// Take the collateral of a loan that was not repaid by its deadline.
+		pub fn liquidate(origin, index: LoanIndex) {
+			let sender = ensure_signed(origin)?;
+
+			let loan = Self::loan(index).ok_or("Loan does not exist")?;
+			ensure!(loan.lender.as_ref() == Some(&sender), "Only lender can liquidate");
+			ensure!(<system::Module<T>>::block_number() >= loan.deadline, "Loan is not overdue");
+
+			<nfts::Module<T>>::transfer_from(Self::account_id(), sender, loan.token_id, Vec::new())?;
+			<Loans<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::Liquidated(index));
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The account holding collateral
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+}
+
+/// Collateral, and the tokens nested in it, are escrowed while held by this module.
+impl<T: Trait> nfts::IsEscrowed<T::NFTIndex> for Module<T> {
+	fn is_escrowed(token_id: T::NFTIndex) -> bool {
+		<nfts::Module<T>>::owner_of(token_id) == Some(Self::account_id())
+	}
+}
+
+/// Tests for Lending module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use support::{assert_ok, assert_noop};
+	use crate::mock::*;
+
+	type LendingModule = Module<Test>;
+
+	fn setup(borrower: u64, lender: u64) {
+		System::set_block_number(1);
+		Balances::make_free_balance_be(&borrower, 50);
+		Balances::make_free_balance_be(&lender, 1000);
+		assert_ok!(NFTs::set_minter(Origin::ROOT, borrower, true));
+		assert_ok!(NFTs::issue_with_uri(Origin::signed(borrower), b"https://this_is_a_test.com".to_vec()));
+	}
+
+	#[test]
+	fn repaid_loan_returns_collateral() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			setup(alice, bob);
+
+			assert_noop!(LendingModule::request(Origin::signed(bob), 0, 100, 110, 10), "'from' account does not own this token");
+			assert_noop!(LendingModule::request(Origin::signed(alice), 0, 100, 90, 10), "Repayment is less than amount");
+			assert_ok!(LendingModule::request(Origin::signed(alice), 0, 100, 110, 10));
+			assert_eq!(NFTs::owner_of(0), Some(LendingModule::account_id()));
+			// The issuer can't expire collateral from under the lender, nor can anyone burn it
+			assert_noop!(NFTs::set_expiry(Origin::signed(alice), 0, Some(5)), "Token is held in escrow");
+			assert_noop!(NFTs::burn(Origin::signed(alice), 0), "Only owner or approved account can burn token");
+			assert_noop!(LendingModule::repay(Origin::signed(alice), 0), "Loan is not funded");
+
+			assert_ok!(LendingModule::fund(Origin::signed(bob), 0));
+			assert_noop!(LendingModule::fund(Origin::signed(bob), 0), "Loan is already funded");
+			assert_noop!(LendingModule::cancel(Origin::signed(alice), 0), "Loan is already funded");
+			assert_eq!(Balances::free_balance(&alice), 150);
+
+			System::set_block_number(5);
+			assert_noop!(LendingModule::liquidate(Origin::signed(bob), 0), "Loan is not overdue");
+			// A failed repayment moves neither the collateral nor any funds
+			Balances::make_free_balance_be(&alice, 100);
+			assert_noop!(LendingModule::repay(Origin::signed(alice), 0), "Not enough balance to repay");
+			assert_eq!(NFTs::owner_of(0), Some(LendingModule::account_id()));
+			assert_eq!(Balances::free_balance(&bob), 900);
+			Balances::make_free_balance_be(&alice, 150);
+			// Nor does repaying a lender whose account has been reaped
+			Balances::make_free_balance_be(&bob, 0);
+			assert_noop!(LendingModule::repay(Origin::signed(alice), 0), "Lender account does not exist");
+			assert_eq!(NFTs::owner_of(0), Some(LendingModule::account_id()));
+			assert_eq!(Balances::free_balance(&alice), 150);
+			assert_eq!(Balances::reserved_balance(&alice), 0);
+			Balances::make_free_balance_be(&bob, 900);
+			assert_ok!(LendingModule::repay(Origin::signed(alice), 0));
+			assert_eq!(Balances::free_balance(&alice), 40);
+			assert_eq!(Balances::free_balance(&bob), 1010);
+			assert_eq!(NFTs::owner_of(0), Some(alice));
+			assert_eq!(LendingModule::loan(0), None);
+		});
+	}
+
+	#[test]
+	fn overdue_loan_is_liquidated() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			setup(alice, bob);
+
+			assert_ok!(NFTs::set_expiry(Origin::signed(alice), 0, Some(10)));
+			assert_noop!(LendingModule::request(Origin::signed(alice), 0, 100, 110, 10), "Collateral expires before the deadline");
+			assert_ok!(NFTs::set_expiry(Origin::signed(alice), 0, Some(11)));
+			assert_ok!(LendingModule::request(Origin::signed(alice), 0, 100, 110, 10));
+			assert_ok!(LendingModule::fund(Origin::signed(bob), 0));
+
+			System::set_block_number(10);
+			assert_noop!(LendingModule::repay(Origin::signed(alice), 0), "Loan is overdue");
+			assert_noop!(LendingModule::liquidate(Origin::signed(alice), 0), "Only lender can liquidate");
+			assert_ok!(LendingModule::liquidate(Origin::signed(bob), 0));
+			assert_eq!(NFTs::owner_of(0), Some(bob));
+			assert_eq!(LendingModule::loan(0), None);
+		});
+	}
+}
@@ -68,6 +68,19 @@ mod kitties;
 
 mod linked_item;
 
+/// Used for the module voting in `./voting.rs`
+mod voting;
+
+/// Used for the module nft_staking in `./nft_staking.rs`
+mod nft_staking;
+
+/// Used for the module lending in `./lending.rs`
+mod lending;
+
+/// Mock runtime shared by the module tests in `./mock.rs`
+#[cfg(test)]
+mod mock;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -101,8 +114,8 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("substrate-nfts"),
 	impl_name: create_runtime_str!("substrate-nfts"),
 	authoring_version: 3,
-	spec_version: 4,
-	impl_version: 4,
+	spec_version: 5,
+	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
 };
 
@@ -255,16 +268,104 @@ impl sudo::Trait for Runtime {
 	type Proposal = Call;
 }
 
+parameter_types! {
+	pub const MaxNestingDepth: u32 = 8;
+	pub const MaxExpiriesPerBlock: u32 = 50;
+	pub const MaxTransferDataLen: u32 = 256;
+	pub const MaxOperators: u32 = 32;
+	pub const MaxProvenanceRecords: u32 = 64;
+	pub const MaxChildren: u32 = 64;
+	pub const MaxBatchSize: u32 = 100;
+	pub const MaxAttributeLen: u32 = 256;
+	pub const MaxAttributes: u32 = 16;
+	pub const MaxMigrationSteps: u32 = 1000;
+}
+
 impl nfts::Trait for Runtime {
 	type Event = Event;
-	// type NFTIndex = primitives::U256;
 	type NFTIndex = u128;
+	type TokenCount = u128;
+	type Currency = Balances;
+	type MaxNestingDepth = MaxNestingDepth;
+	type AdminOrigin = voting::EnsureRootOrTokenHolders<AccountId>;
+	type Signature = Signature;
+	type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+	type OnNftTransfer = Kitties;
+	type Escrow = (Lending, NftStaking);
+	type OnControlledAttributeChange = NftStaking;
+	type MaxTransferDataLen = MaxTransferDataLen;
+	type MaxOperators = MaxOperators;
+	type MaxProvenanceRecords = MaxProvenanceRecords;
+	type MaxChildren = MaxChildren;
+	type MaxBatchSize = MaxBatchSize;
+	type MaxAttributeLen = MaxAttributeLen;
+	type MaxAttributes = MaxAttributes;
+	type MaxMigrationSteps = MaxMigrationSteps;
+}
+
+/// A separate registry for event tickets, with its own storage, events and token ids.
+impl nfts::Trait<nfts::Instance1> for Runtime {
+	type Event = Event;
+	// 256-bit ids, the same width as ERC-721 token ids
+	type NFTIndex = primitives::U256;
+	type TokenCount = u64;
+	type Currency = Balances;
+	type MaxNestingDepth = MaxNestingDepth;
+	type AdminOrigin = system::EnsureRoot<AccountId>;
+	type Signature = Signature;
+	type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+	type OnNftTransfer = ();
+	type Escrow = ();
+	type OnControlledAttributeChange = ();
+	type MaxTransferDataLen = MaxTransferDataLen;
+	type MaxOperators = MaxOperators;
+	type MaxProvenanceRecords = MaxProvenanceRecords;
+	type MaxChildren = MaxChildren;
+	type MaxBatchSize = MaxBatchSize;
+	type MaxAttributeLen = MaxAttributeLen;
+	type MaxAttributes = MaxAttributes;
+	type MaxMigrationSteps = MaxMigrationSteps;
 }
 
 impl kitties::Trait for Runtime {
 	type Event = Event;
 	type KittyIndex = u32;
 	type Currency = Balances;
+	type AccessoryIndex = <Runtime as nfts::Trait>::NFTIndex;
+	type Accessories = NFTs;
+}
+
+parameter_types! {
+	pub const VotingPeriod: BlockNumber = 3 * DAYS;
+	pub const ProposalDeposit: Balance = 100_000_000;
+	pub const Quorum: Perbill = Perbill::from_percent(20);
+	pub const MaxProposalsPerBlock: u32 = 4;
+}
+
+impl voting::Trait for Runtime {
+	type Event = Event;
+	type Origin = Origin;
+	type Proposal = Call;
+	type VotingPeriod = VotingPeriod;
+	type Currency = Balances;
+	type ProposalDeposit = ProposalDeposit;
+	type Quorum = Quorum;
+	type MaxProposalsPerBlock = MaxProposalsPerBlock;
+}
+
+parameter_types! {
+	pub const RewardPerBlock: Balance = 1_000_000;
+}
+
+impl nft_staking::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type RewardPerBlock = RewardPerBlock;
+}
+
+impl lending::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
 }
 
 construct_runtime!(
@@ -281,9 +382,17 @@ construct_runtime!(
 		Balances: balances::{default, Error},
 		Sudo: sudo,
 		// Substrate NFTs module
-		NFTs: nfts::{Module, Storage, Call, Event<T>},
+		NFTs: nfts::{Module, Storage, Call, Event<T>, Config<T>},
+		// Ticket registry, a second instance of the NFTs module
+		TicketNFTs: nfts::<Instance1>::{Module, Storage, Call, Event<T>, Config<T>},
 		// Substrate Kitties module
 		Kitties: kitties::{Module, Storage, Call, Event<T>},
+		// NFT-gated voting module
+		Voting: voting::{Module, Storage, Call, Event<T>, Origin},
+		// NFT and kitty staking module
+		NftStaking: nft_staking::{Module, Storage, Call, Event<T>},
+		// NFT-collateralized loans module
+		Lending: lending::{Module, Storage, Call, Event<T>},
 	}
 );
 
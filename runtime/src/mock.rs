@@ -0,0 +1,155 @@
+//! Mock runtime shared by the tests of the modules built on top of `nfts`.
+
+use primitives::{H256, Blake2Hasher};
+use support::{impl_outer_origin, parameter_types};
+use sr_primitives::{traits::{BlakeTwo256, IdentityLookup, Verify, Lazy}, testing::Header};
+use sr_primitives::weights::Weight;
+use sr_primitives::Perbill;
+use codec::{Encode, Decode};
+use crate::{nfts, kitties, voting, nft_staking, lending};
+
+impl_outer_origin! {
+	pub enum Origin for Test {
+		voting,
+	}
+}
+
+// For testing the modules, we construct most of a mock runtime. This means
+// first constructing a configuration type (`Test`) which `impl`s each of the
+// configuration traits of modules we want to use.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Test;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+}
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type WeightMultiplierUpdate = ();
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+parameter_types! {
+	pub const ExistentialDeposit: u64 = 0;
+	pub const TransferFee: u64 = 0;
+	pub const CreationFee: u64 = 0;
+	pub const TransactionBaseFee: u64 = 0;
+	pub const TransactionByteFee: u64 = 0;
+}
+impl balances::Trait for Test {
+	type Balance = u64;
+	type OnFreeBalanceZero = ();
+	type OnNewAccount = ();
+	type Event = ();
+	type TransactionPayment = ();
+	type TransferPayment = ();
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type TransferFee = TransferFee;
+	type CreationFee = CreationFee;
+	type TransactionBaseFee = TransactionBaseFee;
+	type TransactionByteFee = TransactionByteFee;
+	type WeightToFee = ();
+}
+parameter_types! {
+	pub const MaxNestingDepth: u32 = 3;
+	pub const MaxExpiriesPerBlock: u32 = 3;
+	pub const MaxTransferDataLen: u32 = 8;
+	pub const MaxOperators: u32 = 8;
+	pub const MaxProvenanceRecords: u32 = 8;
+	pub const MaxMigrationSteps: u32 = 8;
+	pub const MaxAttributeLen: u32 = 32;
+	pub const MaxAttributes: u32 = 8;
+	pub const MaxBatchSize: u32 = 8;
+	pub const MaxChildren: u32 = 8;
+	pub const VotingPeriod: u64 = 10;
+	pub const ProposalDeposit: u64 = 5;
+	pub const Quorum: Perbill = Perbill::from_percent(50);
+	pub const MaxProposalsPerBlock: u32 = 2;
+	pub const RewardPerBlock: u64 = 10;
+}
+impl nfts::Trait for Test {
+	type NFTIndex = u128;
+	type TokenCount = u128;
+	type Event = ();
+	type Currency = balances::Module<Test>;
+	type MaxNestingDepth = MaxNestingDepth;
+	type AdminOrigin = voting::EnsureRootOrTokenHolders<u64>;
+	type Signature = TestSignature;
+	type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+	type OnNftTransfer = kitties::Module<Test>;
+	type Escrow = (lending::Module<Test>, nft_staking::Module<Test>);
+	type OnControlledAttributeChange = nft_staking::Module<Test>;
+	type MaxTransferDataLen = MaxTransferDataLen;
+	type MaxOperators = MaxOperators;
+	type MaxProvenanceRecords = MaxProvenanceRecords;
+	type MaxChildren = MaxChildren;
+	type MaxBatchSize = MaxBatchSize;
+	type MaxAttributeLen = MaxAttributeLen;
+	type MaxAttributes = MaxAttributes;
+	type MaxMigrationSteps = MaxMigrationSteps;
+}
+impl kitties::Trait for Test {
+	type KittyIndex = u32;
+	type Currency = balances::Module<Test>;
+	type Event = ();
+	type AccessoryIndex = u128;
+	type Accessories = nfts::Module<Test>;
+}
+impl voting::Trait for Test {
+	type Event = ();
+	type Origin = Origin;
+	type Proposal = nfts::Call<Test>;
+	type VotingPeriod = VotingPeriod;
+	type Currency = balances::Module<Test>;
+	type ProposalDeposit = ProposalDeposit;
+	type Quorum = Quorum;
+	type MaxProposalsPerBlock = MaxProposalsPerBlock;
+}
+impl nft_staking::Trait for Test {
+	type Event = ();
+	type Currency = balances::Module<Test>;
+	type RewardPerBlock = RewardPerBlock;
+}
+impl lending::Trait for Test {
+	type Event = ();
+	type Currency = balances::Module<Test>;
+}
+
+/// Vouchers are not used in these tests.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
+pub struct TestSignature;
+
+impl Verify for TestSignature {
+	type Signer = u64;
+	fn verify<L: Lazy<[u8]>>(&self, _msg: L, _signer: &u64) -> bool {
+		false
+	}
+}
+
+pub type NFTs = nfts::Module<Test>;
+pub type Kitties = kitties::Module<Test>;
+pub type Balances = balances::Module<Test>;
+pub type System = system::Module<Test>;
+
+// This function basically just builds a genesis storage key/value store according to
+// our desired mockup.
+pub fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	nfts::GenesisConfig::<Test>::default().assimilate_storage(&mut t).unwrap();
+	t.into()
+}
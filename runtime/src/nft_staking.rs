@@ -0,0 +1,305 @@
+use support::{
+	decl_module, decl_storage, decl_event, ensure, StorageMap,
+	traits::{Currency, Get},
+};
+use sr_primitives::traits::{Zero, Saturating, AccountIdConversion, SaturatedConversion};
+use sr_primitives::ModuleId;
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use rstd::prelude::*;
+use crate::{nfts::{self, NFTS}, kitties};
+
+/// Staked tokens are escrowed in, and rewards are paid from, this module's account.
+const MODULE_ID: ModuleId = ModuleId(*b"nftstake");
+
+/// Reserved `nfts` attribute holding a token's reward multiplier, as a SCALE encoded `u32` percentage.
+/// Only controllers of the token's issuer can set it.
+pub const MULTIPLIER_ATTRIBUTE: &[u8] = b"staking:multiplier";
+
+/// Multiplier of tokens without the attribute, and of kitties.
+const BASE_MULTIPLIER: u32 = 100;
+
+pub trait Trait: system::Trait + nfts::Trait + kitties::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	type Currency: Currency<Self::AccountId>;
+	/// Reward earned by each staked item per block, before its multiplier.
+	type RewardPerBlock: Get<BalanceOf<Self>>;
+}
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// An item that can be staked. Used as a call argument, so it is always `Debug`.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Asset<NFTIndex, KittyIndex> {
+	Nft(NFTIndex),
+	Kitty(KittyIndex),
+}
+
+type AssetOf<T> = Asset<<T as nfts::Trait>::NFTIndex, <T as kitties::Trait>::KittyIndex>;
+
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct StakeInfo<AccountId, BlockNumber> {
+	pub owner: AccountId,
+	/// Rewards are settled up to this block.
+	pub settled: BlockNumber,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as NftStaking {
+		/// Staked items
+		pub Stakes get(stake_info): map AssetOf<T> => Option<StakeInfo<T::AccountId, T::BlockNumber>>;
+		/// Items staked by an account
+		pub StakedBy get(staked_by): map T::AccountId => Vec<AssetOf<T>>;
+		/// Settled rewards not yet paid out, e.g. of unstaked items
+		pub PendingRewards get(pending_rewards): map T::AccountId => BalanceOf<T>;
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where
+		<T as system::Trait>::AccountId,
+		Asset = AssetOf<T>,
+		Balance = BalanceOf<T>,
+	{
+		/// An item is staked. (owner, asset)
+		Staked(AccountId, Asset),
+		/// An item is unstaked and returned. (owner, asset)
+		Unstaked(AccountId, Asset),
+		/// Rewards are paid out. (owner, amount)
+		Claimed(AccountId, Balance),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Lock an owned token or kitty in escrow to earn rewards.
+		pub fn stake(origin, asset: AssetOf<T>) {
+			let sender = ensure_signed(origin)?;
+
+			if let Asset::Nft(token_id) = asset {
+				ensure!(<nfts::Module<T>>::expires_at(token_id).is_none(), "Expiring tokens can't be staked");
+			}
+
+			// Fails unless the sender owns the item
+			match asset {
+				Asset::Nft(token_id) => <nfts::Module<T>>::transfer_from(sender.clone(), Self::account_id(), token_id, Vec::new())?,
+				Asset::Kitty(kitty_id) => <kitties::Module<T>>::transfer_kitty(&sender, &Self::account_id(), kitty_id)?,
+			}
+
+			<Stakes<T>>::insert(&asset, StakeInfo {
+				owner: sender.clone(),
+				settled: <system::Module<T>>::block_number(),
+			});
+			<StakedBy<T>>::mutate(&sender, |assets| assets.push(asset));
+
+			Self::deposit_event(RawEvent::Staked(sender, asset));
+		}
+
+		/// Return a staked item to its owner. Its rewards are kept for a later `claim`.
+		pub fn unstake(origin, asset: AssetOf<T>) {
+			let sender = ensure_signed(origin)?;
+
+			let info = Self::stake_info(&asset).ok_or("Asset is not staked")?;
+			ensure!(info.owner == sender, "Only owner can unstake");
+
+			match asset {
+				Asset::Nft(token_id) => <nfts::Module<T>>::transfer_from(Self::account_id(), sender.clone(), token_id, Vec::new())?,
+				Asset::Kitty(kitty_id) => <kitties::Module<T>>::transfer_kitty(&Self::account_id(), &sender, kitty_id)?,
+			}
+
+			let reward = Self::accrued(&asset, &info);
+			<PendingRewards<T>>::mutate(&sender, |pending| *pending = pending.saturating_add(reward));
+			<Stakes<T>>::remove(&asset);
+			<StakedBy<T>>::mutate(&sender, |assets| assets.retain(|item| *item != asset));
+
+			Self::deposit_event(RawEvent::Unstaked(sender, asset));
+		}
+
+		/// Pay out the rewards of all items staked by the sender, plus any pending rewards.
+		pub fn claim(origin) {
+			let sender = ensure_signed(origin)?;
+
+			let now = <system::Module<T>>::block_number();
+			// Stakes of tokens burned while staked earn nothing and are dropped
+			let (assets, burned): (Vec<_>, Vec<_>) = Self::staked_by(&sender).into_iter().partition(Self::exists);
+			let mut total = Self::pending_rewards(&sender);
+			for asset in assets.iter() {
+				if let Some(info) = Self::stake_info(asset) {
+					total = total.saturating_add(Self::accrued(asset, &info));
+				}
+			}
+			ensure!(!total.is_zero(), "No rewards to claim");
+
+			// Pay first so nothing is settled if the pool is short
+			<T as Trait>::Currency::transfer(&Self::account_id(), &sender, total)?;
+
+			for asset in assets.iter() {
+				<Stakes<T>>::mutate(asset, |info| if let Some(info) = info {
+					info.settled = now;
+				});
+			}
+			for asset in burned.iter() {
+				<Stakes<T>>::remove(asset);
+			}
+			if !burned.is_empty() {
+				<StakedBy<T>>::insert(&sender, assets);
+			}
+			<PendingRewards<T>>::remove(&sender);
+
+			Self::deposit_event(RawEvent::Claimed(sender, total));
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The account holding staked items and the reward pool
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// The reward multiplier of an item, in percent.
+	pub fn multiplier_of(asset: &AssetOf<T>) -> u32 {
+		match asset {
+			Asset::Nft(token_id) => <nfts::Module<T>>::controlled_attribute((*token_id, MULTIPLIER_ATTRIBUTE.to_vec()))
+				.and_then(|value| Decode::decode(&mut &value[..]).ok())
+				.unwrap_or(BASE_MULTIPLIER),
+			Asset::Kitty(_) => BASE_MULTIPLIER,
+		}
+	}
+
+	/// Whether a staked item still exists. Tokens are escrowed, but another module may still burn them.
+	fn exists(asset: &AssetOf<T>) -> bool {
+		match asset {
+			Asset::Nft(token_id) => <nfts::Module<T>>::is_valid(*token_id),
+			Asset::Kitty(_) => true,
+		}
+	}
+
+	/// Rewards earned by a staked item since it was last settled.
+	fn accrued(asset: &AssetOf<T>, info: &StakeInfo<T::AccountId, T::BlockNumber>) -> BalanceOf<T> {
+		let blocks = <system::Module<T>>::block_number().saturating_sub(info.settled);
+		T::RewardPerBlock::get()
+			.saturating_mul(BalanceOf::<T>::saturated_from(blocks.saturated_into::<u128>()))
+			.saturating_mul(Self::multiplier_of(asset).into())
+			/ BASE_MULTIPLIER.into()
+	}
+
+	/// Items staked by an account, with their current multipliers.
+	pub fn stakes_of(who: &T::AccountId) -> Vec<(AssetOf<T>, u32)> {
+		Self::staked_by(who).into_iter().map(|asset| (asset, Self::multiplier_of(&asset))).collect()
+	}
+}
+
+/// Staked tokens are escrowed while held by this module.
+impl<T: Trait> nfts::IsEscrowed<T::NFTIndex> for Module<T> {
+	fn is_escrowed(token_id: T::NFTIndex) -> bool {
+		<nfts::Module<T>>::owner_of(token_id) == Some(Self::account_id())
+	}
+}
+
+/// A new multiplier only applies from the block it is set, so settle what was earned under the old one.
+impl<T: Trait> nfts::OnControlledAttributeChange<T::NFTIndex> for Module<T> {
+	fn on_controlled_attribute_change(token_id: T::NFTIndex, key: &[u8]) {
+		if key != MULTIPLIER_ATTRIBUTE {
+			return;
+		}
+		let asset = Asset::Nft(token_id);
+		if let Some(mut info) = Self::stake_info(&asset) {
+			let reward = Self::accrued(&asset, &info);
+			<PendingRewards<T>>::mutate(&info.owner, |pending| *pending = pending.saturating_add(reward));
+			info.settled = <system::Module<T>>::block_number();
+			<Stakes<T>>::insert(&asset, info);
+		}
+	}
+}
+
+/// Tests for NftStaking module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use support::{assert_ok, assert_noop};
+	use crate::mock::*;
+
+	type StakingModule = Module<Test>;
+
+	#[test]
+	fn staked_items_earn_rewards_and_are_locked() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			let game = 3;
+			Balances::make_free_balance_be(&StakingModule::account_id(), 1000);
+			System::set_block_number(1);
+			assert_ok!(NFTs::set_minter(Origin::ROOT, alice, true));
+			assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			assert_ok!(Kitties::create(Origin::signed(alice)));
+
+			// The issuer's controller doubles the token's rewards
+			assert_ok!(NFTs::set_controller(Origin::signed(alice), game, true));
+			assert_ok!(NFTs::set_controlled_attribute(Origin::signed(game), 0, MULTIPLIER_ATTRIBUTE.to_vec(), Some(200u32.encode())));
+
+			assert_noop!(StakingModule::stake(Origin::signed(bob), Asset::Nft(0)), "'from' account does not own this token");
+			assert_ok!(StakingModule::stake(Origin::signed(alice), Asset::Nft(0)));
+			assert_ok!(StakingModule::stake(Origin::signed(alice), Asset::Kitty(0)));
+			assert_eq!(StakingModule::stakes_of(&alice), vec![(Asset::Nft(0), 200), (Asset::Kitty(0), 100)]);
+
+			// Staked items can't be moved by their owner
+			assert_noop!(<NFTs as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]), "'from' account does not own this token");
+			assert_noop!(Kitties::transfer(Origin::signed(alice), bob, 0), "Only owner can transfer kitty");
+
+			System::set_block_number(11);
+			assert_ok!(StakingModule::claim(Origin::signed(alice)));
+			assert_eq!(Balances::free_balance(&alice), 300);
+
+			System::set_block_number(16);
+			assert_noop!(StakingModule::unstake(Origin::signed(bob), Asset::Nft(0)), "Only owner can unstake");
+			assert_ok!(StakingModule::unstake(Origin::signed(alice), Asset::Nft(0)));
+			assert_eq!(NFTs::owner_of(0), Some(alice));
+			assert_eq!(StakingModule::pending_rewards(&alice), 100);
+
+			assert_ok!(StakingModule::claim(Origin::signed(alice)));
+			assert_eq!(Balances::free_balance(&alice), 450);
+			assert_noop!(StakingModule::claim(Origin::signed(alice)), "No rewards to claim");
+		});
+	}
+
+	#[test]
+	fn multiplier_changes_and_burned_tokens_are_settled() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let game = 3;
+			Balances::make_free_balance_be(&StakingModule::account_id(), 1000);
+			System::set_block_number(1);
+			assert_ok!(NFTs::set_minter(Origin::ROOT, alice, true));
+			assert_ok!(NFTs::set_controller(Origin::signed(alice), game, true));
+			for _ in 0..3 {
+				assert_ok!(NFTs::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+			}
+
+			assert_ok!(NFTs::set_expiry(Origin::signed(alice), 2, Some(100)));
+			assert_noop!(StakingModule::stake(Origin::signed(alice), Asset::Nft(2)), "Expiring tokens can't be staked");
+			assert_ok!(StakingModule::stake(Origin::signed(alice), Asset::Nft(0)));
+			assert_ok!(StakingModule::stake(Origin::signed(alice), Asset::Nft(1)));
+			assert_noop!(NFTs::set_expiry(Origin::signed(alice), 0, Some(100)), "Token is held in escrow");
+
+			// Blocks 1 to 6 are settled at the old multiplier
+			System::set_block_number(6);
+			assert_ok!(NFTs::set_controlled_attribute(Origin::signed(game), 0, MULTIPLIER_ATTRIBUTE.to_vec(), Some(300u32.encode())));
+			assert_eq!(StakingModule::pending_rewards(&alice), 50);
+
+			// A token burned while staked stops earning
+			assert_ok!(<NFTs as NFTS<_, _>>::_burn(1));
+
+			System::set_block_number(11);
+			assert_ok!(StakingModule::claim(Origin::signed(alice)));
+			assert_eq!(Balances::free_balance(&alice), 200);
+			assert_eq!(StakingModule::staked_by(&alice), vec![Asset::Nft(0)]);
+			assert_eq!(StakingModule::stake_info(&Asset::Nft(1)), None);
+		});
+	}
+}
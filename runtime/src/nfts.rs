@@ -1,23 +1,274 @@
-use sr_primitives::traits::{SimpleArithmetic, Bounded, CheckedAdd, CheckedSub, Member};
+use sr_primitives::traits::{
+    SimpleArithmetic, Bounded, CheckedAdd, CheckedSub, CheckedMul, Member, Zero, One, Saturating,
+    SaturatedConversion,
+    Verify, Hash as HashT,
+};
 use support::{
     decl_module, decl_storage, decl_event, ensure, StorageValue, StorageMap,
-    Parameter, dispatch::Result,
+    Parameter, dispatch::Result, traits::{Currency, ReservableCurrency, Get, EnsureOrigin}, storage::unhashed,
 };
 use system::ensure_signed;
+use codec::{Encode, Decode};
+use runtime_io::blake2_128;
+use primitives::{U256, H256};
 
 
-// use codec::{Encode, Decode};
-// use runtime_io::blake2_128;
 // use system::ensure_signed;
-use rstd::result;
+use rstd::{result, borrow::Borrow};
 use support::dispatch::Vec;
 // use codec::alloc::string::String;
 
 /// The module's configuration trait.
-pub trait Trait: system::Trait {
+pub trait Trait<I: Instance = DefaultInstance>: system::Trait {
     /// The overarching event type.
-    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
-    type NFTIndex: Parameter + Member + SimpleArithmetic + Bounded + Default + Copy;
+    type Event: From<Event<Self, I>> + Into<<Self as system::Trait>::Event>;
+    /// Token ids. Any `TokenId`, including 256-bit `U256` and `H256` ids.
+    type NFTIndex: TokenId;
+    /// Counts of tokens: account balances, the total supply and the supply cap.
+    type TokenCount: Parameter + Member + SimpleArithmetic + Bounded + Default + Copy;
+    /// The currency used to pay for sales and rentals. Payments are reserved before a token moves.
+    type Currency: ReservableCurrency<Self::AccountId>;
+    /// How deep tokens may be nested inside other tokens.
+    type MaxNestingDepth: Get<u32>;
+    /// The origin which may manage issuers, minters and the supply cap.
+    type AdminOrigin: EnsureOrigin<Self::Origin>;
+    /// The signature creators use to sign lazy-mint vouchers, and owners use to sign permits.
+    type Signature: Parameter + Verify<Signer = Self::AccountId>;
+    /// How many expiring tokens `on_initialize` may process per block.
+    type MaxExpiriesPerBlock: Get<u32>;
+    /// Notified of every mint, transfer and burn, and may veto them.
+    type OnNftTransfer: OnNftTransfer<Self::AccountId, Self::NFTIndex>;
+    /// The longest `data` payload `transfer_from` accepts.
+    type MaxTransferDataLen: Get<u32>;
+    /// How many operator grants an owner may hold at once.
+    type MaxOperators: Get<u32>;
+    /// How many provenance records are kept per token. Older records are dropped first.
+    type MaxProvenanceRecords: Get<u32>;
+    /// How many tokens may be nested directly inside one token.
+    type MaxChildren: Get<u32>;
+    /// How many tokens `issue_hidden` may mint at once.
+    type MaxBatchSize: Get<u32>;
+    /// The longest attribute key or value a token may hold.
+    type MaxAttributeLen: Get<u32>;
+    /// How many attributes of each kind a token may hold.
+    type MaxAttributes: Get<u32>;
+    /// Modules holding tokens in escrow, e.g. as loan collateral.
+    type Escrow: IsEscrowed<Self::NFTIndex>;
+    /// Notified before a controlled attribute of a token changes.
+    type OnControlledAttributeChange: OnControlledAttributeChange<Self::NFTIndex>;
+    /// How many token ids one call of `migrate_storage` visits.
+    type MaxMigrationSteps: Get<u32>;
+}
+
+/// A token id. Ids are handed out in order, starting from `Default::default()`.
+pub trait TokenId: Parameter + Member + Default + Copy {
+    /// The id `n` places after this one, or None if the id space is exhausted.
+    fn checked_advance(&self, n: u32) -> Option<Self>;
+}
+
+macro_rules! impl_token_id_for_uints {
+    ( $( $t:ty ),+ ) => {
+        $(
+            impl TokenId for $t {
+                fn checked_advance(&self, n: u32) -> Option<Self> {
+                    <$t>::checked_add(*self, n.into())
+                }
+            }
+        )+
+    };
+}
+
+impl_token_id_for_uints!(u32, u64, u128);
+
+impl TokenId for U256 {
+    fn checked_advance(&self, n: u32) -> Option<Self> {
+        U256::checked_add(*self, U256::from(n))
+    }
+}
+
+/// Hash-shaped ids, read as big-endian 256-bit numbers so they match ERC-721 `uint256` ids.
+impl TokenId for H256 {
+    fn checked_advance(&self, n: u32) -> Option<Self> {
+        let next = U256::from_big_endian(self.as_bytes()).checked_advance(n)?;
+        let mut bytes = [0u8; 32];
+        next.to_big_endian(&mut bytes);
+        Some(H256::from(bytes))
+    }
+}
+
+/// A handler for token movements in other modules.
+pub trait OnNftTransfer<AccountId, NFTIndex> {
+    /// Called before `token_id` moves. `from` is None for mints and `to` is None for burns.
+    /// `data` is the payload passed to `transfer_from`, and empty otherwise.
+    /// Returning an error vetoes the movement.
+    /// Handlers in a tuple run in order, so a handler should not write storage it relies on a later one to accept.
+    fn on_nft_transfer(from: Option<&AccountId>, to: Option<&AccountId>, token_id: NFTIndex, data: &[u8]) -> Result;
+}
+
+impl<AccountId, NFTIndex> OnNftTransfer<AccountId, NFTIndex> for () {
+    fn on_nft_transfer(_: Option<&AccountId>, _: Option<&AccountId>, _: NFTIndex, _: &[u8]) -> Result { Ok(()) }
+}
+
+macro_rules! impl_on_nft_transfer_for_tuples {
+    ( $( $handler:ident ),+ ) => {
+        impl<AccountId, NFTIndex: Copy, $( $handler: OnNftTransfer<AccountId, NFTIndex> ),+>
+            OnNftTransfer<AccountId, NFTIndex> for ( $( $handler, )+ )
+        {
+            fn on_nft_transfer(from: Option<&AccountId>, to: Option<&AccountId>, token_id: NFTIndex, data: &[u8]) -> Result {
+                $( $handler::on_nft_transfer(from, to, token_id, data)?; )+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_on_nft_transfer_for_tuples!(A);
+impl_on_nft_transfer_for_tuples!(A, B);
+impl_on_nft_transfer_for_tuples!(A, B, C);
+impl_on_nft_transfer_for_tuples!(A, B, C, D);
+impl_on_nft_transfer_for_tuples!(A, B, C, D, E);
+impl_on_nft_transfer_for_tuples!(A, B, C, D, E, F);
+impl_on_nft_transfer_for_tuples!(A, B, C, D, E, F, G);
+impl_on_nft_transfer_for_tuples!(A, B, C, D, E, F, G, H);
+
+/// Implemented by modules that hold tokens in escrow on behalf of their owners.
+/// The issuer of an escrowed token can't change its expiry, so it can't be burned out from under the escrow.
+pub trait IsEscrowed<NFTIndex> {
+    /// Whether `token_id` is held in escrow.
+    fn is_escrowed(token_id: NFTIndex) -> bool;
+}
+
+impl<NFTIndex> IsEscrowed<NFTIndex> for () {
+    fn is_escrowed(_: NFTIndex) -> bool { false }
+}
+
+macro_rules! impl_is_escrowed_for_tuples {
+    ( $( $escrow:ident ),+ ) => {
+        impl<NFTIndex: Copy, $( $escrow: IsEscrowed<NFTIndex> ),+> IsEscrowed<NFTIndex> for ( $( $escrow, )+ ) {
+            fn is_escrowed(token_id: NFTIndex) -> bool {
+                $( $escrow::is_escrowed(token_id) )||+
+            }
+        }
+    };
+}
+
+impl_is_escrowed_for_tuples!(A);
+impl_is_escrowed_for_tuples!(A, B);
+impl_is_escrowed_for_tuples!(A, B, C);
+impl_is_escrowed_for_tuples!(A, B, C, D);
+
+/// Implemented by modules that act on controlled attributes, e.g. to settle what was earned under the old value.
+pub trait OnControlledAttributeChange<NFTIndex> {
+    /// Called before the controlled attribute `key` of `token_id` is set or cleared.
+    fn on_controlled_attribute_change(token_id: NFTIndex, key: &[u8]);
+}
+
+impl<NFTIndex> OnControlledAttributeChange<NFTIndex> for () {
+    fn on_controlled_attribute_change(_: NFTIndex, _: &[u8]) {}
+}
+
+/// Domain separator for lazy-mint vouchers, so a voucher signature can't be reused elsewhere.
+const VOUCHER_CONTEXT: &[u8] = b"nfts:voucher";
+
+/// Domain separator for signed approvals.
+const PERMIT_CONTEXT: &[u8] = b"nfts:permit";
+
+/// The storage layout version. Bump it, and add a step to `migrate_step`, whenever
+/// the encoding of existing storage changes.
+pub const STORAGE_VERSION: u32 = 1;
+
+type BalanceOf<T, I> = <<T as Trait<I>>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// The direct owner of a token: an account, or another token it is nested in.
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub enum Ownership<AccountId, NFTIndex> {
+    Account(AccountId),
+    Token(NFTIndex),
+}
+
+/// ERC-4907 style user role. The user may use the token until `expires`, but does not own it.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct UserInfo<AccountId, BlockNumber> {
+    pub user: AccountId,
+    /// The block at which the user role lapses.
+    pub expires: BlockNumber,
+}
+
+/// A change of ownership in a token's history.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct ProvenanceRecord<AccountId, BlockNumber, Balance> {
+    /// None for mints.
+    pub from: Option<AccountId>,
+    /// None for burns.
+    pub to: Option<AccountId>,
+    pub block: BlockNumber,
+    /// The price paid, if the token was sold.
+    pub price: Option<Balance>,
+}
+
+/// A single-token approval, valid until `expires` if set.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct Approval<AccountId, BlockNumber> {
+    pub approved: AccountId,
+    pub expires: Option<BlockNumber>,
+}
+
+/// An allowlisted drop. `root` is the Merkle root over `(account, quota)` leaves;
+/// each allowlisted account may mint up to `quota` tokens with `uri` on behalf of `issuer`.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct AllowlistDrop<AccountId, Hash> {
+    pub issuer: AccountId,
+    pub root: Hash,
+    pub uri: Vec<u8>,
+}
+
+/// A primary sale. Buyers pay `price` to `beneficiary` for each token minted
+/// between blocks `start` and `end` inclusive, under the issuer role of `issuer`.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct SaleConfig<AccountId, Balance, BlockNumber> {
+    /// Sequence number of the sale, so per-account counts start fresh for each sale.
+    pub id: u32,
+    pub issuer: AccountId,
+    pub beneficiary: AccountId,
+    pub price: Balance,
+    pub start: BlockNumber,
+    pub end: BlockNumber,
+    pub per_account_cap: u32,
+    pub total_cap: u32,
+    /// Tokens sold so far.
+    pub minted: u32,
+    pub uri: Vec<u8>,
+}
+
+/// A batch of tokens minted with placeholder URIs. The final URIs are committed
+/// to up front and revealed later.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct HiddenBatch<AccountId, NFTIndex, Hash> {
+    pub issuer: AccountId,
+    /// The first token of the batch. Tokens of a batch have consecutive ids.
+    pub first: NFTIndex,
+    pub count: u32,
+    /// Hash of `(uris, salt)`, see `Module::reveal_commitment`.
+    pub commitment: Hash,
+    /// If set, URIs are assigned to tokens in an order drawn from this seed on reveal.
+    /// It is taken when the batch is minted, so the issuer can't pick it by timing the reveal.
+    pub shuffle_seed: Option<Hash>,
+}
+
+/// Terms under which the owner offers a token for rent.
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct RentalOffer<Balance, BlockNumber> {
+    pub price_per_block: Balance,
+    /// The longest rental period the owner accepts, in blocks.
+    pub max_duration: BlockNumber,
 }
 
 pub trait NFTS<AccountId, NFTIndex> {
@@ -64,14 +315,15 @@ pub trait NFTS<AccountId, NFTIndex> {
     
     /*************************************************
     Function:       // issue_with_uri 发行代币
-    Description:
+    Description:    // minter须拥有发行者或铸造者角色，发行后总量不能超过最大供应量
     Input:
+                    minter  发行代币的用户ID
                     to      接收代币用户ID
                     uri     代币附加信息uri地址
     Output:
     Return:         Result    执行结果
     *************************************************/
-    fn _issue_with_uri(who: &AccountId, uri: Vec<u8>) -> result::Result<(), &'static str>;
+    fn _issue_with_uri(minter: &AccountId, to: &AccountId, uri: Vec<u8>) -> result::Result<(), &'static str>;
 
 
     /*************************************************
@@ -94,7 +346,7 @@ pub trait NFTS<AccountId, NFTIndex> {
 
 
 
-impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
+impl<T: Trait<I>, I: Instance> NFTS<T::AccountId, T::NFTIndex> for Module<T, I> {
     /*************************************************
     Function:       // transfer_from转账
     Description:    // 函数功能、性能等的描述
@@ -108,27 +360,53 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
 
     *************************************************/
     fn transfer_from(from: T::AccountId, to: T::AccountId, token_id: T::NFTIndex, data: Vec<u8>) -> Result {
-        let owner = match Self::owner_of(token_id) {
+        Self::ensure_migrated()?;
+        let owner = match Self::root_owner_of(token_id) {
             Some(c) => c,
             None => return Err("No owner for this token"),
         };
 
         ensure!(owner == from, "'from' account does not own this token");
+        ensure!(data.len() as u32 <= T::MaxTransferDataLen::get(), "Transfer data too long");
+
+        // Nested tokens travel with their parent
+        let descendants = Self::descendants_of(token_id);
+        Self::ensure_transferable(token_id, &descendants)?;
+        let amount: T::TokenCount = (descendants.len() as u32 + 1).into();
 
         let balance_of_from = Self::balance_of(&from);
         let balance_of_to = Self::balance_of(&to);
 
-        let new_balance_of_from = balance_of_from.checked_sub(&1.into())
+        let new_balance_of_from = balance_of_from.checked_sub(&amount)
             .ok_or("Transfer causes underflow of 'from' token balance")?;
-        let new_balance_of_to = balance_of_to.checked_add(&1.into())
+        let new_balance_of_to = balance_of_to.checked_add(&amount)
             .ok_or("Transfer causes overflow of 'to' token balance")?;
+        Self::notify_transfer(Some(&from), Some(&to), token_id, &descendants, &data)?;
+
+        Self::set_balance(&from, new_balance_of_from);
+        Self::set_balance(&to, new_balance_of_to);
+        Self::detach(token_id);
+        <TokenOwner<T, I>>::insert(&token_id, Ownership::Account(to.clone()));
+        Self::reset_token_state(token_id)?;
+        Self::checkpoint_owner(token_id, Some(to.clone()));
+        Self::record_provenance(token_id, Some(from.clone()), Some(to.clone()));
+        for child in descendants {
+            Self::reset_token_state(child)?;
+            Self::checkpoint_owner(child, Some(to.clone()));
+            Self::record_provenance(child, Some(from.clone()), Some(to.clone()));
+        }
 
-        <OwnedTokensCount<T>>::insert(&from, new_balance_of_from);
-        <OwnedTokensCount<T>>::insert(&to, new_balance_of_to);
-        <TokenOwner<T>>::insert(&token_id, &to);
-        Self::_clear_approval(token_id)?;
-
-        Self::deposit_event(RawEvent::Transfer(Some(from), Some(to), token_id));
+        Self::deposit_event(RawEvent::Transfer(Some(from.clone()), Some(to.clone()), token_id));
+        if !data.is_empty() {
+            // Indexed by from, to and token id, so wallets can look these up without scanning blocks
+            let topics = [
+                T::Hashing::hash_of(&from),
+                T::Hashing::hash_of(&to),
+                T::Hashing::hash_of(&token_id),
+            ];
+            let event = <T as Trait<I>>::Event::from(RawEvent::TransferWithData(from, to, token_id, data));
+            <system::Module<T>>::deposit_event_indexed(&topics, event.into());
+        }
         Ok(())
     }
 
@@ -143,23 +421,8 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
     Return:         Result    执行结果
     *************************************************/
     fn _approve(origin: T::AccountId, to: T::AccountId, token_id: T::NFTIndex) -> Result {
-        
-        //Get the Owner of the tokenId
-        let  owner_of_token_id = <TokenOwner<T>>::get(token_id);
-        // check msg sender 
-        ensure!(owner_of_token_id!= Some(origin.clone()),"You can not approve the token,Because You did not own it!");
-
-        // check msg sender 
-        ensure!(to!= origin,"You can not set approval for yourself!");
-
-        // Set approved state
-        <TokenApprovals<T>>::insert(token_id, to.clone());
-
-        // deposit event
-        Self::deposit_event(RawEvent::Approval(origin, to, token_id));
-        
-        // Done
-        Ok(())
+        Self::ensure_migrated()?;
+        Self::do_approve(origin, to, token_id, None)
     }
 
     /*************************************************
@@ -173,50 +436,25 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
     Return:         Result    执行结果
     *************************************************/
     fn _set_approval_for_all(origin: T::AccountId, to: T::AccountId, approved: bool) -> Result {
-        
-        // check msg sender 
-        ensure!(to!=origin,"You can not set approval for yourself!");
-
-        // Set approved state
-        <OperatorApprovals<T>>::insert((origin.clone(), to.clone()), approved);
-
-        // deposit event
-        Self::deposit_event(RawEvent::ApprovalForAll(origin, to, approved));
-        
-        // Done
-        Ok(())
+        Self::ensure_migrated()?;
+        Self::do_set_approval_for_all(origin, to, approved, None)
     }
 
 
     /*************************************************
     Function:       // issue_with_uri 发行代币
-    Description:
+    Description:    // minter须拥有发行者或铸造者角色，发行后总量不能超过最大供应量
     Input:
+                    minter  发行代币的用户ID
                     to      接收代币用户ID
                     uri     代币附加信息uri地址
     Output:
     Return:         Result    执行结果
     *************************************************/
-    fn _issue_with_uri(who: &T::AccountId, uri: Vec<u8>) -> Result {
-        let token_id = Self::total_supply();
-
-        ensure!(!<TokenOwner<T>>::exists(token_id), "Token hash already exists");
-        let balance_of = Self::balance_of(who);
-
-        let new_balance_of = match balance_of.checked_add(&1.into()) {
-            Some(c) => c,
-            None => return Err("Overflow adding a new token to account balance"),
-        };
-
-        Self::supply_increase()?;
-        <TokenUri<T>>::insert(token_id, uri);
-
-        <TokenOwner<T>>::insert(token_id, who);
-        <OwnedTokensCount<T>>::insert(who, new_balance_of);
-        Nonce::mutate(|n| *n += 1);
-        Self::deposit_event(RawEvent::Transfer(None, Some(who.clone()), token_id));
-
-        Ok(())
+    fn _issue_with_uri(minter: &T::AccountId, who: &T::AccountId, uri: Vec<u8>) -> Result {
+        Self::ensure_migrated()?;
+        Self::ensure_can_issue(minter)?;
+        Self::do_issue(minter, who, uri)
     }
 
     /*************************************************
@@ -228,34 +466,52 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
     Return:         Result    执行结果
     *************************************************/
     fn _burn(token_id: T::NFTIndex) -> Result {
-        let owner = match Self::owner_of(token_id) {
+        Self::ensure_migrated()?;
+        let owner = match Self::root_owner_of(token_id) {
             Some(c) => c,
             None => return Err("No owner for this token"),
         };
 
+        ensure!(Self::children_of(token_id).is_empty(), "Token still owns other tokens");
+        ensure!(!Self::is_rented(token_id), "Token is rented out");
+
         let balance_of = Self::balance_of(&owner);
 
         let new_balance_of = match balance_of.checked_sub(&1.into()) {
             Some(c) => c,
             None => return Err("Underflow subtracting a token to account balance"),
         };
+        T::OnNftTransfer::on_nft_transfer(Some(&owner), None, token_id, &[])?;
 
         Self::supply_decrease()?;
-        <TokenUri<T>>::remove(token_id);
+        <TokenUri<T, I>>::remove(token_id);
         
-        Self::_clear_approval(token_id)?;
+        Self::reset_token_state(token_id)?;
+        <TokenIssuer<T, I>>::remove(token_id);
+        <RedeemedTokens<T, I>>::remove(token_id);
+        <ResaleCaps<T, I>>::remove(token_id);
+        Self::unschedule_expiry(token_id);
+        for key in <AttributeKeys<T, I>>::take(token_id) {
+            <Attributes<T, I>>::remove((token_id, key));
+        }
+        for key in <ControlledAttributeKeys<T, I>>::take(token_id) {
+            <ControlledAttributes<T, I>>::remove((token_id, key));
+        }
 
-        <OwnedTokensCount<T>>::insert(&owner, new_balance_of);
-        <TokenOwner<T>>::remove(token_id);
+        Self::set_balance(&owner, new_balance_of);
+        Self::detach(token_id);
+        <TokenOwner<T, I>>::remove(token_id);
+        Self::checkpoint_owner(token_id, None);
+        Self::record_provenance(token_id, Some(owner.clone()), None);
 
-        Nonce::mutate(|n| *n += 1);
+        <Nonce<I>>::mutate(|n| *n += 1);
         Self::deposit_event(RawEvent::Transfer(Some(owner), None, token_id));
 
         Ok(())
     }
 
     fn _clear_approval(token_id: T::NFTIndex) -> Result{
-        <TokenApprovals<T>>::remove(token_id);
+        <TokenApprovals<T, I>>::remove(token_id);
 
         Ok(())
     }
@@ -270,7 +526,7 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
             None => return Err("Overflow when adding new token to total supply"),
         };
 
-        <TotalSupply<T>>::put(new_total_supply);
+        Self::set_total_supply(new_total_supply);
 
         Ok(())
     }
@@ -283,171 +539,2712 @@ impl<T: Trait> NFTS<T::AccountId, T::NFTIndex> for Module<T> {
             None => return Err("Underflow removing token from total supply"),
         };
 
-        <TotalSupply<T>>::put(new_total_supply);
+        Self::set_total_supply(new_total_supply);
 
         Ok(())
     }
 }
 
 decl_storage! {
-	trait Store for Module<T: Trait> as NFTS {
+	trait Store for Module<T: Trait<I>, I: Instance = DefaultInstance> as NFTS {
 		//某个用户拥有的代币数量
-		OwnedTokensCount get(balance_of): map T::AccountId => T::NFTIndex;
-		//通过代币ID查找用户
-		TokenOwner get(owner_of): map T::NFTIndex => Option<T::AccountId>;
-		//查找代币的授权委托情况
-		TokenApprovals get(get_approved): map T::NFTIndex => Option<T::AccountId>;
-		//查找用户的高级授权情况
-		OperatorApprovals get(is_approved_for_all): map (T::AccountId, T::AccountId) => bool;
+		OwnedTokensCount get(balance_of): map T::AccountId => T::TokenCount;
+		//通过代币ID查找直接拥有者，可以是用户，也可以是另一个代币
+		TokenOwner get(token_owner): map T::NFTIndex => Option<Ownership<T::AccountId, T::NFTIndex>>;
+		//某个代币直接拥有的子代币，最多MaxChildren个
+		TokenChildren get(children_of): map T::NFTIndex => Vec<T::NFTIndex>;
+		//查找代币的授权委托情况，包含可选的到期区块
+		TokenApprovals get(token_approval): map T::NFTIndex => Option<Approval<T::AccountId, T::BlockNumber>>;
+		//查找用户的高级授权情况，Some(expires)表示已授权，expires为None表示永不过期
+		OperatorGrants get(operator_approval): map (T::AccountId, T::AccountId) => Option<Option<T::BlockNumber>>;
+		//升级前（版本0）的高级授权，无法遍历，由所有者调用migrate_operators迁移到OperatorGrants；迁移前仍然有效
+		OperatorApprovals: map (T::AccountId, T::AccountId) => bool;
+		//调用过revoke_all_operators的用户，其未迁移的版本0高级授权作废
+		LegacyOperatorsRevoked get(legacy_operators_revoked): map T::AccountId => bool;
+		//限定范围的高级授权，只对指定发行者发行的代币有效 (owner, operator, issuer)
+		ScopedOperatorApprovals get(scoped_operator_approval): map (T::AccountId, T::AccountId, T::AccountId) => Option<Option<T::BlockNumber>>;
+		//用户的高级授权列表 (operator, scope)，scope为None表示全部代币
+		Operators get(operators_of): map T::AccountId => Vec<(T::AccountId, Option<T::AccountId>)>;
+		//代币的流转记录，最多保留MaxProvenanceRecords条，销毁后仍保留
+		Provenance get(provenance_of): map T::NFTIndex => Vec<ProvenanceRecord<T::AccountId, T::BlockNumber, BalanceOf<T, I>>>;
 		//当前的代币总量
-		TotalSupply get(total_supply): T::NFTIndex;
+		TotalSupply get(total_supply): T::TokenCount;
+		//下一个发行代币的ID，销毁的代币ID不会被重复使用
+		NextTokenId get(next_token_id): T::NFTIndex;
 		// token id => token uri
 		// TokenUri get(token_uri): map T::NFTIndex => Option<Vec<u8>>;
 		TokenUri get(token_uri): map T::NFTIndex => Vec<u8>;
 		// Not a part of the ERC721 specification, but recommended to add.
 		Nonce: u64;
+		//存储结构的版本，升级后由migrate_storage分步迁移到STORAGE_VERSION，迁移完成前代币相关调用均被拒绝
+		StorageVersion get(storage_version): u32;
+		//版本0到1迁移的进度 (下一个代币ID, 已迁移的代币数)
+		MigrationCursor get(migration_cursor): Option<(T::NFTIndex, T::TokenCount)>;
+		//拥有发行者角色的用户，可以发行代币并管理铸造者
+		Issuers get(is_issuer): map T::AccountId => bool;
+		//拥有铸造者角色的用户，可以发行代币；值为授予该角色的发行者，None表示由管理员授予
+		Minters get(minter_grantor): map T::AccountId => Option<Option<T::AccountId>>;
+		//最大供应量，None表示不限制
+		MaxSupply get(max_supply): Option<T::TokenCount>;
+		//已使用的代币凭证 (creator, nonce)，防止重放
+		UsedVoucherNonces get(is_voucher_used): map (T::AccountId, u64) => bool;
+		//用户下一个签名授权(permit)的序号，防止重放
+		PermitNonces get(permit_nonce): map T::AccountId => u64;
+		//每个发行者的白名单发行配置
+		Allowlists get(allowlist): map T::AccountId => Option<AllowlistDrop<T::AccountId, T::Hash>>;
+		//白名单用户已领取的数量 (issuer, root, account) => count
+		AllowlistClaims get(allowlist_claims): map (T::AccountId, T::Hash, T::AccountId) => u32;
+		//每个发行者的一级市场发售配置
+		Sales get(sale): map T::AccountId => Option<SaleConfig<T::AccountId, BalanceOf<T, I>, T::BlockNumber>>;
+		//已配置的发售次数
+		SaleCount get(sale_count): u32;
+		//每个用户在某次发售中已购买的数量 (sale_id, account) => count
+		SaleMintedBy get(sale_minted_by): map (u32, T::AccountId) => u32;
+		//未揭示元数据的代币批次
+		HiddenBatches get(hidden_batch): map u32 => Option<HiddenBatch<T::AccountId, T::NFTIndex, T::Hash>>;
+		//已创建的代币批次数量
+		HiddenBatchCount get(hidden_batch_count): u32;
+		//代币的发行者
+		TokenIssuer get(issuer_of): map T::NFTIndex => Option<T::AccountId>;
+		//发行者指定的核销员 (issuer, verifier)，可以核销该发行者的门票
+		Verifiers get(is_verifier): map (T::AccountId, T::AccountId) => bool;
+		//已核销（检票）的门票，不可再转账
+		RedeemedTokens get(is_redeemed): map T::NFTIndex => bool;
+		//发行者设置的最高转售价格
+		ResaleCaps get(resale_cap): map T::NFTIndex => Option<BalanceOf<T, I>>;
+		//代币的出售价格，None表示不出售
+		TokenPrices get(token_price): map T::NFTIndex => Option<BalanceOf<T, I>>;
+		//代币的有效期，到期后代币失效并被自动销毁
+		TokenExpiry get(expires_at): map T::NFTIndex => Option<T::BlockNumber>;
+		//发行者允许持有者延长有效期的最大区块数
+		ExtensionPolicies get(extension_policy): map T::AccountId => Option<T::BlockNumber>;
+		//待处理的到期代币，按到期区块索引，每个区块最多MaxExpiriesPerBlock - 1个
+		ExpiryQueue get(expiry_queue): map T::BlockNumber => Vec<T::NFTIndex>;
+		//下一个待处理的到期区块
+		ExpiryCursor get(expiry_cursor): Option<T::BlockNumber>;
+		//代币属性，由持有者维护
+		Attributes get(attribute): map (T::NFTIndex, Vec<u8>) => Option<Vec<u8>>;
+		//代币属性的键，销毁代币时据此清理属性，最多MaxAttributes个
+		AttributeKeys get(attribute_keys): map T::NFTIndex => Vec<Vec<u8>>;
+		//代币的保留属性，只能由发行者指定的控制者（如游戏账户）维护
+		ControlledAttributes get(controlled_attribute): map (T::NFTIndex, Vec<u8>) => Option<Vec<u8>>;
+		//代币保留属性的键，最多MaxAttributes个
+		ControlledAttributeKeys get(controlled_attribute_keys): map T::NFTIndex => Vec<Vec<u8>>;
+		//发行者指定的控制者 (issuer, controller)
+		Controllers get(is_controller): map (T::AccountId, T::AccountId) => bool;
+		//用户代币数量的历史记录 (account, index) => (block, balance)
+		BalanceCheckpoints get(balance_checkpoint): map (T::AccountId, u32) => Option<(T::BlockNumber, T::TokenCount)>;
+		//用户代币数量历史记录的条数
+		BalanceCheckpointCount get(balance_checkpoint_count): map T::AccountId => u32;
+		//代币拥有者的历史记录 (token_id, index) => (block, owner)
+		OwnerCheckpoints get(owner_checkpoint): map (T::NFTIndex, u32) => Option<(T::BlockNumber, Option<T::AccountId>)>;
+		//代币拥有者历史记录的条数
+		OwnerCheckpointCount get(owner_checkpoint_count): map T::NFTIndex => u32;
+		//代币总量的历史记录 index => (block, total_supply)
+		SupplyCheckpoints get(supply_checkpoint): map u32 => Option<(T::BlockNumber, T::TokenCount)>;
+		//代币总量历史记录的条数
+		SupplyCheckpointCount get(supply_checkpoint_count): u32;
+		//代币的使用者及到期区块（ERC-4907），到期后自动失效
+		TokenUsers get(user_info): map T::NFTIndex => Option<UserInfo<T::AccountId, T::BlockNumber>>;
+		//代币的出租报价，None表示不出租
+		RentalOffers get(rental_offer): map T::NFTIndex => Option<RentalOffer<BalanceOf<T, I>, T::BlockNumber>>;
+		//已付费租出的代币及租期结束区块，租期内拥有者不能转移、嵌套或销毁代币
+		RentedUntil get(rented_until): map T::NFTIndex => Option<T::BlockNumber>;
 
 
     }
+	add_extra_genesis {
+		//创世代币 (owner, uri)，发行者记为owner
+		config(tokens): Vec<(T::AccountId, Vec<u8>)>;
+		//创世高级授权 (owner, operator)，永不过期
+		config(operators): Vec<(T::AccountId, T::AccountId)>;
+		//创世发行者，可以发行代币并管理铸造者
+		config(issuers): Vec<T::AccountId>;
+		build(|config: &GenesisConfig<T, I>| {
+			<StorageVersion<I>>::put(STORAGE_VERSION);
+			for issuer in config.issuers.iter() {
+				<Issuers<T, I>>::insert(issuer, true);
+			}
+			for (owner, uri) in config.tokens.iter() {
+				<Module<T, I>>::do_issue(owner, owner, uri.clone())
+					.expect("genesis tokens must be issuable; qed");
+			}
+			for (owner, operator) in config.operators.iter() {
+				<Module<T, I>>::set_operator(owner, operator, None, true, None)
+					.expect("genesis operators must be valid; qed");
+			}
+		});
+	}
 }
 
 decl_module! {
-    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
-		fn deposit_event() = default;
+    pub struct Module<T: Trait<I>, I: Instance = DefaultInstance> for enum Call where origin: T::Origin {
+		fn deposit_event<T, I>() = default;
+
+		fn on_initialize(now: T::BlockNumber) {
+			Self::sweep_expired(now);
+		}
+
+		/// Run the next step of a pending storage migration, visiting at most `MaxMigrationSteps` token ids.
+		/// Anyone may call this after a runtime upgrade, until `StorageMigrated(STORAGE_VERSION)` is emitted.
+		pub fn migrate_storage(origin) -> Result {
+			ensure_signed(origin)?;
+			Self::migrate_step()
+		}
 
 		pub fn issue_with_uri(origin,  uri: Vec<u8>) ->Result{
 			let sender = ensure_signed(origin)?;
-			Self::_issue_with_uri(&sender, uri.clone())
+			Self::ensure_migrated()?;
+			Self::_issue_with_uri(&sender, &sender, uri.clone())
 		}
 		pub fn burn(origin, token_id:T::NFTIndex) -> Result{ 
 			let sender = ensure_signed(origin)?;
+			Self::ensure_migrated()?;
+			ensure!(Self::_is_approved_or_owner(&sender, token_id), "Only owner or approved account can burn token");
 			Self::_burn(token_id)
 		}
         pub fn approve(origin, to: T::AccountId, token_id: T::NFTIndex) -> Result{
             let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
             Self::_approve(sender, to, token_id)
         }
         fn set_approval_for_all(origin, to: T::AccountId, approved: bool) -> Result {
             let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
             Self::_set_approval_for_all(sender, to, approved)
         }
-    }
-}
+        /// Approve `to` to transfer a single token until block `expires`.
+        pub fn approve_until(origin, to: T::AccountId, token_id: T::NFTIndex, expires: T::BlockNumber) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+            Self::do_approve(sender, to, token_id, Some(expires))
+        }
+        /// Approve `to` as operator of all tokens of the sender until block `expires`.
+        pub fn set_approval_for_all_until(origin, to: T::AccountId, expires: T::BlockNumber) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+            Self::do_set_approval_for_all(sender, to, true, Some(expires))
+        }
 
+        /// Approve or revoke `to` as operator of the sender's tokens issued by `issuer`, optionally until block `expires`.
+        pub fn set_scoped_approval_for_all(
+            origin,
+            to: T::AccountId,
+            issuer: T::AccountId,
+            approved: bool,
+            expires: Option<T::BlockNumber>
+        ) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+            Self::set_operator(&sender, &to, Some(issuer.clone()), approved, expires)?;
 
-decl_event!(
-	pub enum Event<T> where
-		<T as system::Trait>::AccountId,
-		<T as Trait>::NFTIndex,
-	{
-		//转账事件
-        Transfer(Option<AccountId>, Option<AccountId>, NFTIndex),
-		//普通授权事件
-        Approval(AccountId, AccountId, NFTIndex),
-		//高级授权事件
-        ApprovalForAll(AccountId, AccountId, bool),
-	}
-);
+            Self::deposit_event(RawEvent::ScopedApprovalForAll(sender, to, issuer, approved, expires));
+            Ok(())
+        }
 
+        /// Revoke every operator grant of the sender, global or scoped, including grants
+        /// made before storage version 1 that have not been migrated.
+        pub fn revoke_all_operators(origin) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            for (operator, scope) in <Operators<T, I>>::take(&sender) {
+                match scope {
+                    None => <OperatorGrants<T, I>>::remove((sender.clone(), operator)),
+                    Some(issuer) => <ScopedOperatorApprovals<T, I>>::remove((sender.clone(), operator, issuer)),
+                }
+            }
+            // Legacy grants can't be enumerated, so they are voided rather than removed
+            <LegacyOperatorsRevoked<T, I>>::insert(&sender, true);
+
+            Self::deposit_event(RawEvent::OperatorsRevoked(sender));
+            Ok(())
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        /// Carry over the sender's global grants made before storage version 1, which can't be enumerated on chain.
+        /// They become grants without expiry and are listed in `operators_of`. Revoked or unknown grants are skipped.
+        pub fn migrate_operators(origin, operators: Vec<T::AccountId>) -> Result {
+            let owner = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(!Self::legacy_operators_revoked(&owner), "Legacy operators were revoked");
+
+            let mut listed = Self::operators_of(&owner);
+            let mut migrated = Vec::new();
+            for operator in operators {
+                let key = (owner.clone(), operator.clone());
+                // Revoked grants were stored as `false`, and grants made since the upgrade take precedence
+                if <OperatorApprovals<T, I>>::get(&key) && !<OperatorGrants<T, I>>::exists(&key) && !migrated.contains(&operator) {
+                    migrated.push(operator);
+                }
+            }
+            ensure!((listed.len() + migrated.len()) as u32 <= T::MaxOperators::get(), "Too many operators");
+
+            for operator in migrated {
+                let key = (owner.clone(), operator.clone());
+                <OperatorApprovals<T, I>>::remove(&key);
+                <OperatorGrants<T, I>>::insert(&key, None);
+                listed.push((operator, None));
+            }
+            <Operators<T, I>>::insert(&owner, listed);
+            Ok(())
+        }
 
-    use runtime_io::with_externalities;
-    use primitives::{H256, Blake2Hasher};
-    use support::{impl_outer_origin, parameter_types};
-    use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
-    use sr_primitives::weights::Weight;
-    use sr_primitives::Perbill;
+        /// Approve `spender` for a token with a signature from its owner, so the owner needs no funds.
+        /// The signature covers the owner's next permit nonce and is valid up to block `deadline`.
+        pub fn permit(
+            origin,
+            owner: T::AccountId,
+            spender: T::AccountId,
+            token_id: T::NFTIndex,
+            deadline: T::BlockNumber,
+            signature: T::Signature
+        ) -> Result {
+            ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(<system::Module<T>>::block_number() <= deadline, "Permit has expired");
+            let nonce = Self::permit_nonce(&owner);
+            let payload = Self::permit_payload(&owner, &spender, token_id, nonce, deadline);
+            ensure!(signature.verify(&payload[..], &owner), "Invalid permit signature");
+
+            Self::do_approve(owner.clone(), spender, token_id, None)?;
+            <PermitNonces<T, I>>::insert(&owner, nonce + 1);
+            Ok(())
+        }
 
-    impl_outer_origin! {
-            pub enum Origin for Test {}
-    }
-    #[derive(Clone, Eq, PartialEq, Debug)]
-    pub struct Test;
-    parameter_types! {
-            pub const BlockHashCount: u64 = 250;
-            pub const MaximumBlockWeight: Weight = 1024;
-            pub const MaximumBlockLength: u32 = 2 * 1024;
-            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-    }
-    impl system::Trait for Test {
-            type Origin = Origin;
-            type Call = ();
-            type Index = u64;
-            type BlockNumber = u64;
-            type Hash = H256;
-            type Hashing = BlakeTwo256;
-            type AccountId = u64;
-            type Lookup = IdentityLookup<Self::AccountId>;
-            type Header = Header;
-            type WeightMultiplierUpdate = ();
-            type Event = ();
-            type BlockHashCount = BlockHashCount;
-            type MaximumBlockWeight = MaximumBlockWeight;
-            type MaximumBlockLength = MaximumBlockLength;
-            type AvailableBlockRatio = AvailableBlockRatio;
-            type Version = ();
-    }
-    impl Trait for Test {
-            type NFTIndex = u128;
-            type Event = ();
-    }
-    type TestModule = Module<Test>;
-    // This function basically just builds a genesis storage key/value store according to
-    // our desired mockup.
-    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
-            system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
-    }
+        /// Grant or revoke the issuer role.
+        pub fn set_issuer(origin, who: T::AccountId, enabled: bool) -> Result {
+            T::AdminOrigin::ensure_origin(origin)?;
+            Self::ensure_migrated()?;
 
-    #[test]
-    fn test_issue() {
-        with_externalities(&mut new_test_ext(), || {
-        });
-    }
-    #[test]
-    fn test_burn() {
-        with_externalities(&mut new_test_ext(), || {
-        });
-    }
-    #[test]
-    fn test_approve() {
-        with_externalities(&mut new_test_ext(), || {
-            let alice = 0;
-            let bob = 1;
-            let new_tk_id = TestModule::total_supply();
-            TestModule::issue_with_uri(Origin::signed(alice),b"https://this_is_a_test.com".to_vec());
-            assert_eq!(TestModule::balance_of(&alice), 1);
-            // TestModule::approve(Origin::signed(alice), alice, new_tk_id);
-            // assert_eq!(TestModule::get_approved(&new_tk_id), Some(alice));
-            assert_eq!(TestModule::total_supply(), 1);
-            TestModule::approve(Origin::signed(alice), bob, new_tk_id);
-            assert_eq!(TestModule::get_approved(&new_tk_id), Some(bob));
+            if enabled {
+                <Issuers<T, I>>::insert(&who, true);
+            } else {
+                <Issuers<T, I>>::remove(&who);
+            }
 
-        });
-    }
-    #[test]
-    fn test_transfer_from() {
-        with_externalities(&mut new_test_ext(), || {
-        });
-    }
-    #[test]
-    fn test_set_approval_for_all() {
-        with_externalities(&mut new_test_ext(), || {
-            let alice = 0;
-            let bob = 1;
-            // let new_tk_id = TestModule::total_supply();
-            // TestModule::issue_with_uri(Origin::signed(alice),b"https://this_is_a_test.com".to_vec());
-            // assert_eq!(TestModule::balance_of(&alice), 1);
-            // assert_eq!(TestModule::total_supply(), 1);
-            TestModule::set_approval_for_all(Origin::signed(alice), bob, true);
-            assert_eq!(TestModule::is_approved_for_all((alice,bob)), true);
-        });
-    }
-    #[test]
-    fn test_safe_transfer_from() {
-        with_externalities(&mut new_test_ext(), || {
-        });
-    }
-}
\ No newline at end of file
+            Self::deposit_event(RawEvent::IssuerSet(who, enabled));
+            Ok(())
+        }
+
+        /// Grant or revoke the minter role. Callable by the admin origin or an issuer.
+        /// An issuer can only manage the minters it granted, and they lose the role with it.
+        pub fn set_minter(origin, who: T::AccountId, enabled: bool) -> Result {
+            let grantor = match T::AdminOrigin::try_origin(origin) {
+                Ok(_) => None,
+                Err(origin) => {
+                    let sender = ensure_signed(origin)?;
+                    ensure!(Self::is_issuer(&sender), "Only admin or issuer can manage minting");
+                    Some(sender)
+                }
+            };
+            Self::ensure_migrated()?;
+
+            if let (Some(sender), Some(current)) = (&grantor, Self::minter_grantor(&who)) {
+                ensure!(current.as_ref() == Some(sender), "Minter was granted by another account");
+            }
+
+            if enabled {
+                <Minters<T, I>>::insert(&who, grantor);
+            } else {
+                <Minters<T, I>>::remove(&who);
+            }
+
+            Self::deposit_event(RawEvent::MinterSet(who, enabled));
+            Ok(())
+        }
+
+        /// Set the maximum total supply. None to remove the cap.
+        pub fn set_max_supply(origin, max_supply: Option<T::TokenCount>) -> Result {
+            T::AdminOrigin::ensure_origin(origin)?;
+            Self::ensure_migrated()?;
+
+            if let Some(max_supply) = max_supply {
+                ensure!(max_supply >= Self::total_supply(), "Max supply is below total supply");
+                <MaxSupply<T, I>>::put(max_supply);
+            } else {
+                <MaxSupply<T, I>>::kill();
+            }
+
+            Self::deposit_event(RawEvent::MaxSupplySet(max_supply));
+            Ok(())
+        }
+
+        /// Mint a token from a voucher signed off-chain by `creator`, paying `price` to the creator.
+        /// The creator must hold the issuer or minter role.
+        pub fn redeem_voucher(
+            origin,
+            creator: T::AccountId,
+            uri: Vec<u8>,
+            price: BalanceOf<T, I>,
+            nonce: u64,
+            recipient: Option<T::AccountId>,
+            signature: T::Signature
+        ) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let payload = Self::voucher_payload(&uri, &price, nonce, &recipient);
+            ensure!(signature.verify(&payload[..], &creator), "Invalid voucher signature");
+            ensure!(!Self::is_voucher_used((creator.clone(), nonce)), "Voucher already redeemed");
+            if let Some(recipient) = recipient {
+                ensure!(recipient == sender, "Voucher is reserved for another account");
+            }
+            ensure!(T::Currency::free_balance(&sender) >= price, "Not enough balance to redeem voucher");
+
+            Self::reserve_payment(&sender, &creator, price)?;
+            let token_id = Self::next_token_id();
+            if let Err(e) = Self::_issue_with_uri(&creator, &sender, uri) {
+                T::Currency::unreserve(&sender, price);
+                return Err(e);
+            }
+            <UsedVoucherNonces<T, I>>::insert((creator.clone(), nonce), true);
+            Self::pay_reserved(&sender, &creator, price);
+            if !price.is_zero() {
+                Self::record_sale_price(token_id, price);
+            }
+
+            Self::deposit_event(RawEvent::VoucherRedeemed(creator, sender, token_id, price));
+            Ok(())
+        }
+
+        /// Open the sender's allowlisted drop with the given Merkle root, or close it with None.
+        /// Each issuer runs at most one drop; tokens minted from it are issued under the sender's issuer role.
+        pub fn set_allowlist(origin, root: Option<T::Hash>, uri: Vec<u8>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::is_issuer(&sender), "Only issuer can manage allowlist");
+
+            if let Some(root) = root {
+                <Allowlists<T, I>>::insert(&sender, AllowlistDrop { issuer: sender.clone(), root, uri });
+            } else {
+                <Allowlists<T, I>>::remove(&sender);
+            }
+
+            Self::deposit_event(RawEvent::AllowlistSet(sender, root));
+            Ok(())
+        }
+
+        /// Mint one token from `issuer`'s allowlisted drop. `proof` shows that `(sender, quota)`
+        /// is a leaf of the drop's Merkle root.
+        pub fn allowlist_mint(origin, issuer: T::AccountId, quota: u32, proof: Vec<T::Hash>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let allowlist = Self::allowlist(&issuer).ok_or("No allowlist drop is open")?;
+            let leaf = Self::allowlist_leaf(&sender, quota);
+            ensure!(Self::verify_merkle_proof(&allowlist.root, leaf, &proof), "Invalid allowlist proof");
+
+            let key = (issuer, allowlist.root, sender.clone());
+            let claimed = Self::allowlist_claims(&key);
+            ensure!(claimed < quota, "Allowlist quota used up");
+
+            let token_id = Self::next_token_id();
+            Self::_issue_with_uri(&allowlist.issuer, &sender, allowlist.uri)?;
+            <AllowlistClaims<T, I>>::insert(&key, claimed + 1);
+
+            Self::deposit_event(RawEvent::AllowlistClaimed(sender, token_id));
+            Ok(())
+        }
+
+        /// Configure the sender's primary sale, replacing their previous one.
+        /// Tokens are minted under the sender's issuer role.
+        pub fn set_sale(
+            origin,
+            beneficiary: T::AccountId,
+            price: BalanceOf<T, I>,
+            start: T::BlockNumber,
+            end: T::BlockNumber,
+            per_account_cap: u32,
+            total_cap: u32,
+            uri: Vec<u8>
+        ) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::is_issuer(&sender), "Only issuer can manage sale");
+            ensure!(start <= end, "Sale ends before it starts");
+
+            let id = Self::sale_count();
+            <SaleCount<I>>::put(id + 1);
+            <Sales<T, I>>::insert(&sender, SaleConfig {
+                id,
+                issuer: sender.clone(),
+                beneficiary,
+                price,
+                start,
+                end,
+                per_account_cap,
+                total_cap,
+                minted: 0,
+                uri,
+            });
+
+            Self::deposit_event(RawEvent::SaleSet(sender, id));
+            Ok(())
+        }
+
+        /// Close the sender's primary sale.
+        pub fn close_sale(origin) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let sale = Self::sale(&sender).ok_or("No sale is configured")?;
+            <Sales<T, I>>::remove(&sender);
+
+            Self::deposit_event(RawEvent::SaleClosed(sender, sale.id));
+            Ok(())
+        }
+
+        /// Buy one token from the primary sale of `issuer`.
+        pub fn mint(origin, issuer: T::AccountId) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let mut sale = Self::sale(&issuer).ok_or("No sale is configured")?;
+            let now = <system::Module<T>>::block_number();
+            ensure!(now >= sale.start, "Sale has not started");
+            ensure!(now <= sale.end, "Sale has ended");
+            ensure!(sale.minted < sale.total_cap, "Sale is sold out");
+
+            let key = (sale.id, sender.clone());
+            let minted_by = Self::sale_minted_by(&key);
+            ensure!(minted_by < sale.per_account_cap, "Sale per-account cap reached");
+            ensure!(T::Currency::free_balance(&sender) >= sale.price, "Not enough balance to buy from sale");
+
+            Self::reserve_payment(&sender, &sale.beneficiary, sale.price)?;
+            let token_id = Self::next_token_id();
+            if let Err(e) = Self::_issue_with_uri(&sale.issuer, &sender, sale.uri.clone()) {
+                T::Currency::unreserve(&sender, sale.price);
+                return Err(e);
+            }
+
+            <SaleMintedBy<T, I>>::insert(&key, minted_by + 1);
+            sale.minted += 1;
+            let price = sale.price;
+            let beneficiary = sale.beneficiary.clone();
+            <Sales<T, I>>::insert(&issuer, sale);
+
+            Self::pay_reserved(&sender, &beneficiary, price);
+            if !price.is_zero() {
+                Self::record_sale_price(token_id, price);
+            }
+
+            Self::deposit_event(RawEvent::SaleMinted(sender, token_id, price));
+            Ok(())
+        }
+
+        /// Mint `count` tokens to `to` with a placeholder URI, committing to their final URIs.
+        pub fn issue_hidden(
+            origin,
+            to: T::AccountId,
+            count: u32,
+            placeholder_uri: Vec<u8>,
+            commitment: T::Hash,
+            shuffle: bool
+        ) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(count > 0, "Batch must not be empty");
+            ensure!(count <= T::MaxBatchSize::get(), "Batch is too large");
+            ensure!(Self::can_mint(&sender), "Only issuer or minter can issue token");
+            // Check everything a mint could fail on up front, so the batch is minted whole or not at all
+            let first = Self::next_token_id();
+            first.checked_advance(count).ok_or("Overflow allocating a new token id")?;
+            let new_total_supply = Self::total_supply().checked_add(&count.into())
+                .ok_or("Overflow when adding new token to total supply")?;
+            if let Some(max_supply) = Self::max_supply() {
+                ensure!(new_total_supply <= max_supply, "Token supply cap reached");
+            }
+            Self::balance_of(&to).checked_add(&count.into())
+                .ok_or("Overflow adding a new token to account balance")?;
+
+            for _ in 0..count {
+                Self::do_issue(&sender, &to, placeholder_uri.clone())?;
+            }
+
+            let batch_id = Self::hidden_batch_count();
+            <HiddenBatchCount<I>>::put(batch_id + 1);
+            <HiddenBatches<T, I>>::insert(batch_id, HiddenBatch {
+                issuer: sender.clone(),
+                first,
+                count,
+                commitment,
+                shuffle_seed: if shuffle { Some(<system::Module<T>>::random_seed()) } else { None },
+            });
+
+            Self::deposit_event(RawEvent::HiddenBatchIssued(sender, batch_id, first, count));
+            Ok(())
+        }
+
+        /// Reveal the URIs of a hidden batch. They must match the commitment made at mint time.
+        pub fn reveal(origin, batch_id: u32, uris: Vec<Vec<u8>>, salt: Vec<u8>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let batch = Self::hidden_batch(batch_id).ok_or("Batch does not exist")?;
+            ensure!(batch.issuer == sender, "Only the issuer can reveal the batch");
+            ensure!(uris.len() as u32 == batch.count, "Wrong number of URIs");
+            ensure!(Self::reveal_commitment(&uris, &salt) == batch.commitment, "URIs do not match commitment");
+
+            let mut order: Vec<u32> = (0..batch.count).collect();
+            if let Some(seed) = batch.shuffle_seed {
+                for i in (1..order.len()).rev() {
+                    let random = u128::from_le_bytes((seed, batch_id, i as u32).using_encoded(blake2_128));
+                    order.swap(i, (random % (i as u128 + 1)) as usize);
+                }
+            }
+
+            for (i, uri) in order.into_iter().zip(uris) {
+                // Every id in the batch was allocated at mint time, so this can't run out
+                if let Some(token_id) = batch.first.checked_advance(i) {
+                    // Burned tokens stay burned
+                    if <TokenUri<T, I>>::exists(token_id) {
+                        <TokenUri<T, I>>::insert(token_id, uri);
+                    }
+                }
+            }
+            <HiddenBatches<T, I>>::remove(batch_id);
+
+            Self::deposit_event(RawEvent::BatchRevealed(batch_id));
+            Ok(())
+        }
+
+        /// Set when a token expires. None to make it permanent. Only the token's issuer may do this.
+        pub fn set_expiry(origin, token_id: T::NFTIndex, expires_at: Option<T::BlockNumber>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::issuer_of(token_id) == Some(sender.clone()), "Only the issuer can set expiry");
+            ensure!(!Self::is_expired(token_id), "Token has expired");
+            ensure!(!T::Escrow::is_escrowed(token_id), "Token is held in escrow");
+            // Once the token is sold or rented out, its holder can count on the expiry it came with
+            if Self::root_owner_of(token_id) != Some(sender) || Self::is_rented(token_id) {
+                let extends = match (Self::expires_at(token_id), expires_at) {
+                    (_, None) => true,
+                    (Some(current), Some(new)) => new >= current,
+                    (None, Some(_)) => false,
+                };
+                ensure!(extends, "Expiry can only be extended once the token is sold or rented");
+            }
+
+            if let Some(expires_at) = expires_at {
+                ensure!(expires_at > <system::Module<T>>::block_number(), "Expiry must be in the future");
+                Self::schedule_expiry(token_id, expires_at)?;
+            } else {
+                Self::unschedule_expiry(token_id);
+            }
+
+            Self::deposit_event(RawEvent::ExpirySet(token_id, expires_at));
+            Ok(())
+        }
+
+        /// Allow owners of tokens issued by the sender to extend their expiry up to
+        /// `max_extension` blocks ahead of the current block. None to forbid extensions.
+        pub fn set_extension_policy(origin, max_extension: Option<T::BlockNumber>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            if let Some(max_extension) = max_extension {
+                <ExtensionPolicies<T, I>>::insert(&sender, max_extension);
+            } else {
+                <ExtensionPolicies<T, I>>::remove(&sender);
+            }
+
+            Self::deposit_event(RawEvent::ExtensionPolicySet(sender, max_extension));
+            Ok(())
+        }
+
+        /// Extend the expiry of an owned token, within the policy of its issuer.
+        pub fn extend_expiry(origin, token_id: T::NFTIndex, expires_at: T::BlockNumber) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::owner_of(token_id) == Some(sender), "Only owner can extend expiry");
+            let current = Self::expires_at(token_id).ok_or("Token does not expire")?;
+            ensure!(!Self::is_expired(token_id), "Token has expired");
+            ensure!(expires_at > current, "Expiry can only be extended");
+
+            let issuer = Self::issuer_of(token_id).ok_or("Token has no issuer")?;
+            let max_extension = Self::extension_policy(&issuer).ok_or("Issuer does not allow extensions")?;
+            let limit = <system::Module<T>>::block_number().saturating_add(max_extension);
+            ensure!(expires_at <= limit, "Extension exceeds issuer policy");
+
+            Self::schedule_expiry(token_id, expires_at)?;
+
+            Self::deposit_event(RawEvent::ExpirySet(token_id, Some(expires_at)));
+            Ok(())
+        }
+
+        /// Set or clear an attribute of an owned token. None to clear.
+        pub fn set_attribute(origin, token_id: T::NFTIndex, key: Vec<u8>, value: Option<Vec<u8>>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::owner_of(token_id) == Some(sender.clone()), "Only owner can set attribute");
+
+            let mut keys = Self::attribute_keys(token_id);
+            Self::update_attribute_keys(&mut keys, &key, &value)?;
+
+            let attribute = (token_id, key.clone());
+            if let Some(ref value) = value {
+                <Attributes<T, I>>::insert(&attribute, value);
+            } else {
+                <Attributes<T, I>>::remove(&attribute);
+            }
+            <AttributeKeys<T, I>>::insert(token_id, keys);
+
+            Self::deposit_event(RawEvent::AttributeSet(sender, token_id, key, value));
+            Ok(())
+        }
+
+        /// Allow or disallow `controller` to update the reserved attributes of tokens issued by the sender.
+        pub fn set_controller(origin, controller: T::AccountId, enabled: bool) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let key = (sender.clone(), controller.clone());
+            if enabled {
+                <Controllers<T, I>>::insert(&key, true);
+            } else {
+                <Controllers<T, I>>::remove(&key);
+            }
+
+            Self::deposit_event(RawEvent::ControllerSet(sender, controller, enabled));
+            Ok(())
+        }
+
+        /// Set or clear a reserved attribute. Only controllers of the token's issuer may do this.
+        pub fn set_controlled_attribute(origin, token_id: T::NFTIndex, key: Vec<u8>, value: Option<Vec<u8>>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let issuer = Self::issuer_of(token_id).ok_or("Token has no issuer")?;
+            ensure!(Self::is_controller((issuer, sender.clone())), "Only controller can set reserved attribute");
+
+            let mut keys = Self::controlled_attribute_keys(token_id);
+            Self::update_attribute_keys(&mut keys, &key, &value)?;
+            T::OnControlledAttributeChange::on_controlled_attribute_change(token_id, &key);
+
+            let attribute = (token_id, key.clone());
+            if let Some(ref value) = value {
+                <ControlledAttributes<T, I>>::insert(&attribute, value);
+            } else {
+                <ControlledAttributes<T, I>>::remove(&attribute);
+            }
+            <ControlledAttributeKeys<T, I>>::insert(token_id, keys);
+
+            Self::deposit_event(RawEvent::ControlledAttributeSet(sender, token_id, key, value));
+            Ok(())
+        }
+
+        /// Allow or disallow `verifier` to redeem tickets issued by the sender.
+        pub fn set_verifier(origin, verifier: T::AccountId, enabled: bool) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let key = (sender.clone(), verifier.clone());
+            if enabled {
+                <Verifiers<T, I>>::insert(&key, true);
+            } else {
+                <Verifiers<T, I>>::remove(&key);
+            }
+
+            Self::deposit_event(RawEvent::VerifierSet(sender, verifier, enabled));
+            Ok(())
+        }
+
+        /// Check in a ticket. Redeemed tickets can no longer be transferred.
+        pub fn redeem_ticket(origin, token_id: T::NFTIndex) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+            let issuer = Self::issuer_of(token_id).ok_or("Token has no issuer")?;
+            ensure!(Self::is_verifier((issuer, sender.clone())), "Only verifier can redeem ticket");
+            ensure!(!Self::is_redeemed(token_id), "Ticket has been redeemed");
+
+            <RedeemedTokens<T, I>>::insert(token_id, true);
+            <TokenPrices<T, I>>::remove(token_id);
+
+            Self::deposit_event(RawEvent::TicketRedeemed(sender, owner, token_id));
+            Ok(())
+        }
+
+        /// Set the highest price the token may be resold for. None to remove the cap.
+        pub fn set_resale_cap(origin, token_id: T::NFTIndex, cap: Option<BalanceOf<T, I>>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::issuer_of(token_id) == Some(sender), "Only the issuer can set resale cap");
+
+            if let Some(cap) = cap {
+                <ResaleCaps<T, I>>::insert(token_id, cap);
+            } else {
+                <ResaleCaps<T, I>>::remove(token_id);
+            }
+
+            Self::deposit_event(RawEvent::ResaleCapSet(token_id, cap));
+            Ok(())
+        }
+
+        /// Set a price for a token for sale
+        /// None to delist the token
+        pub fn ask(origin, token_id: T::NFTIndex, price: Option<BalanceOf<T, I>>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::owner_of(token_id) == Some(sender.clone()), "Only owner can set price for token");
+
+            if let Some(price) = price {
+                Self::ensure_transferable(token_id, &Self::descendants_of(token_id))?;
+                Self::ensure_within_resale_cap(token_id, price)?;
+                <TokenPrices<T, I>>::insert(token_id, price);
+            } else {
+                <TokenPrices<T, I>>::remove(token_id);
+            }
+
+            Self::deposit_event(RawEvent::Ask(sender, token_id, price));
+            Ok(())
+        }
+
+        /// Buy a token listed for sale, paying at most `price`.
+        pub fn buy(origin, token_id: T::NFTIndex, price: BalanceOf<T, I>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+            ensure!(owner != sender, "You can not buy your own token");
+
+            let token_price = Self::token_price(token_id).ok_or("Token not for sale")?;
+            ensure!(price >= token_price, "Price is too low");
+            Self::ensure_within_resale_cap(token_id, token_price)?;
+            Self::ensure_transferable(token_id, &Self::descendants_of(token_id))?;
+
+            // Hold the price until the transfer, which the transfer hook may still refuse, has gone through
+            Self::reserve_payment(&sender, &owner, token_price)?;
+            if let Err(e) = Self::transfer_from(owner.clone(), sender.clone(), token_id, Vec::new()) {
+                T::Currency::unreserve(&sender, token_price);
+                return Err(e);
+            }
+            Self::pay_reserved(&sender, &owner, token_price);
+            Self::record_sale_price(token_id, token_price);
+
+            Self::deposit_event(RawEvent::Sold(owner, sender, token_id, token_price));
+            Ok(())
+        }
+
+        /// Nest a token inside `parent`. The token and everything nested in it then belong to
+        /// the owner of `parent`, so the sender must be approved for both. Use `unnest` to take it back out.
+        pub fn nest(origin, token_id: T::NFTIndex, parent: T::NFTIndex) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::_is_approved_or_owner(&sender, token_id), "Only owner or approved account can nest token");
+            ensure!(Self::_is_approved_or_owner(&sender, parent), "Only owner or approved account can nest into parent");
+            ensure!(token_id != parent, "Token can not own itself");
+            ensure!((Self::children_of(parent).len() as u32) < T::MaxChildren::get(), "Parent has too many children");
+
+            let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+            let new_owner = Self::owner_of(parent).ok_or("Parent token does not exist")?;
+
+            let ancestors = Self::ancestors_of(parent);
+            ensure!(!ancestors.contains(&token_id), "Nesting would create a cycle");
+
+            let (descendants, height) = Self::subtree_of(token_id);
+            Self::ensure_transferable(token_id, &descendants)?;
+            ensure!(ancestors.len() as u32 + 1 + height <= T::MaxNestingDepth::get(), "Nesting too deep");
+
+            if owner != new_owner {
+                let amount: T::TokenCount = (descendants.len() as u32 + 1).into();
+                let new_balance_of_from = Self::balance_of(&owner).checked_sub(&amount)
+                    .ok_or("Transfer causes underflow of 'from' token balance")?;
+                let new_balance_of_to = Self::balance_of(&new_owner).checked_add(&amount)
+                    .ok_or("Transfer causes overflow of 'to' token balance")?;
+                Self::notify_transfer(Some(&owner), Some(&new_owner), token_id, &descendants, &[])?;
+
+                Self::set_balance(&owner, new_balance_of_from);
+                Self::set_balance(&new_owner, new_balance_of_to);
+                Self::reset_token_state(token_id)?;
+                Self::checkpoint_owner(token_id, Some(new_owner.clone()));
+                Self::record_provenance(token_id, Some(owner.clone()), Some(new_owner.clone()));
+                for child in descendants {
+                    Self::reset_token_state(child)?;
+                    Self::checkpoint_owner(child, Some(new_owner.clone()));
+                    Self::record_provenance(child, Some(owner.clone()), Some(new_owner.clone()));
+                }
+                Self::deposit_event(RawEvent::Transfer(Some(owner), Some(new_owner), token_id));
+            }
+
+            Self::detach(token_id);
+            <TokenOwner<T, I>>::insert(token_id, Ownership::Token(parent));
+            <TokenChildren<T, I>>::mutate(parent, |children| children.push(token_id));
+
+            Self::deposit_event(RawEvent::Nested(token_id, parent));
+            Ok(())
+        }
+
+        /// Take a nested token out of its parent. It stays with the same owner.
+        pub fn unnest(origin, token_id: T::NFTIndex) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::_is_approved_or_owner(&sender, token_id), "Only owner or approved account can unnest token");
+            let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+            match Self::token_owner(token_id) {
+                Some(Ownership::Token(_)) => {},
+                _ => return Err("Token is not nested"),
+            }
+
+            Self::detach(token_id);
+            <TokenOwner<T, I>>::insert(token_id, Ownership::Account(owner));
+
+            Self::deposit_event(RawEvent::Unnested(token_id));
+            Ok(())
+        }
+
+        /// Set the user of a token until block `expires`. The owner keeps ownership.
+        pub fn set_user(origin, token_id: T::NFTIndex, user: T::AccountId, expires: T::BlockNumber) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::_is_approved_or_owner(&sender, token_id), "Only owner or approved account can set user");
+            ensure!(Self::user_of(token_id).is_none(), "Token is already in use");
+            ensure!(expires > <system::Module<T>>::block_number(), "Expiry must be in the future");
+
+            Self::do_set_user(token_id, user, expires);
+            Ok(())
+        }
+
+        /// Offer a token for rent, paid per block up front.
+        /// None to withdraw the offer.
+        pub fn ask_rent(origin, token_id: T::NFTIndex, price_per_block: Option<BalanceOf<T, I>>, max_duration: T::BlockNumber) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            ensure!(Self::owner_of(token_id) == Some(sender.clone()), "Only owner can offer token for rent");
+
+            if let Some(price_per_block) = price_per_block {
+                ensure!(!max_duration.is_zero(), "Rental duration must be positive");
+                <RentalOffers<T, I>>::insert(token_id, RentalOffer { price_per_block, max_duration });
+            } else {
+                <RentalOffers<T, I>>::remove(token_id);
+            }
+
+            Self::deposit_event(RawEvent::RentAsk(sender, token_id, price_per_block, max_duration));
+            Ok(())
+        }
+
+        /// Rent a token for `duration` blocks, paying the owner up front.
+        pub fn rent(origin, token_id: T::NFTIndex, duration: T::BlockNumber, max_price_per_block: BalanceOf<T, I>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::ensure_migrated()?;
+
+            let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+            ensure!(owner != sender, "You can not rent your own token");
+
+            let offer = Self::rental_offer(token_id).ok_or("Token not for rent")?;
+            ensure!(max_price_per_block >= offer.price_per_block, "Price is too low");
+            ensure!(!duration.is_zero(), "Rental duration must be positive");
+            ensure!(duration <= offer.max_duration, "Rental duration is too long");
+            ensure!(Self::user_of(token_id).is_none(), "Token is already in use");
+
+            let expires = <system::Module<T>>::block_number().checked_add(&duration)
+                .ok_or("Overflow calculating rental expiry")?;
+            ensure!(Self::expires_at(token_id).map_or(true, |expires_at| expires <= expires_at), "Rental outlasts the token");
+            let cost = offer.price_per_block
+                .checked_mul(&BalanceOf::<T, I>::saturated_from(duration.saturated_into::<u128>()))
+                .ok_or("Overflow calculating rental cost")?;
+
+            T::Currency::transfer(&sender, &owner, cost)?;
+
+            Self::do_set_user(token_id, sender.clone(), expires);
+            <RentedUntil<T, I>>::insert(token_id, expires);
+
+            Self::deposit_event(RawEvent::Rented(owner, sender, token_id, expires, cost));
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait<I>, I: Instance> Module<T, I> {
+    /// Whether `who` may issue new tokens.
+    pub fn can_mint(who: &T::AccountId) -> bool {
+        Self::is_issuer(who) || Self::is_minter(who)
+    }
+
+    /// Whether `who` holds a minter role whose grantor still holds its own.
+    pub fn is_minter(who: &T::AccountId) -> bool {
+        match Self::minter_grantor(who) {
+            Some(Some(issuer)) => Self::is_issuer(&issuer),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// Check that `minter` may issue one more token right now.
+    fn ensure_can_issue(minter: &T::AccountId) -> Result {
+        ensure!(Self::can_mint(minter), "Only issuer or minter can issue token");
+        if let Some(max_supply) = Self::max_supply() {
+            ensure!(Self::total_supply() < max_supply, "Token supply cap reached");
+        }
+        Ok(())
+    }
+
+    /// Hold `amount` of `payer`'s balance for `payee`. Once held, `pay_reserved` can't fail,
+    /// so the payment can be taken before the token changes hands and settled after.
+    fn reserve_payment(payer: &T::AccountId, payee: &T::AccountId, amount: BalanceOf<T, I>) -> Result {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        ensure!(!T::Currency::total_balance(payee).is_zero(), "Payee account does not exist");
+        T::Currency::reserve(payer, amount)
+    }
+
+    /// Hand a payment held by `reserve_payment` over to `payee`.
+    fn pay_reserved(payer: &T::AccountId, payee: &T::AccountId, amount: BalanceOf<T, I>) {
+        if !amount.is_zero() {
+            let _ = T::Currency::repatriate_reserved(payer, payee, amount);
+        }
+    }
+
+    /// Binds signed payloads to this chain and this instance of the module, so a signature
+    /// can't be replayed on another chain or another registry.
+    fn signing_domain() -> (T::Hash, &'static [u8]) {
+        (<system::Module<T>>::block_hash(T::BlockNumber::zero()), I::PREFIX.as_bytes())
+    }
+
+    /// The payload a creator signs to authorize minting `uri` for `price`.
+    /// With `recipient` set, only that account can redeem the voucher.
+    pub fn voucher_payload(
+        uri: &[u8],
+        price: &BalanceOf<T, I>,
+        nonce: u64,
+        recipient: &Option<T::AccountId>,
+    ) -> Vec<u8> {
+        (VOUCHER_CONTEXT, Self::signing_domain(), uri, price, nonce, recipient).encode()
+    }
+
+    /// The message an owner signs to permit `spender` to transfer a token.
+    pub fn permit_payload(
+        owner: &T::AccountId,
+        spender: &T::AccountId,
+        token_id: T::NFTIndex,
+        nonce: u64,
+        deadline: T::BlockNumber,
+    ) -> Vec<u8> {
+        (PERMIT_CONTEXT, Self::signing_domain(), owner, spender, token_id, nonce, deadline).encode()
+    }
+
+    /// The Merkle leaf for an allowlisted account and its quota.
+    pub fn allowlist_leaf(who: &T::AccountId, quota: u32) -> T::Hash {
+        T::Hashing::hash_of(&(who, quota))
+    }
+
+    /// Check a Merkle proof, hashing each pair of nodes in sorted order.
+    pub fn verify_merkle_proof(root: &T::Hash, leaf: T::Hash, proof: &[T::Hash]) -> bool {
+        let computed = proof.iter().fold(leaf, |node, sibling| {
+            if node.as_ref() <= sibling.as_ref() {
+                T::Hashing::hash_of(&(node, sibling))
+            } else {
+                T::Hashing::hash_of(&(sibling, node))
+            }
+        });
+        computed == *root
+    }
+
+    /// The commitment to a hidden batch's final URIs.
+    pub fn reveal_commitment(uris: &[Vec<u8>], salt: &[u8]) -> T::Hash {
+        T::Hashing::hash_of(&(uris, salt))
+    }
+
+    /// The account at the root of a token, or None if it does not exist or has expired.
+    /// Expired tokens are burned by `on_initialize`, but may linger until the sweep reaches them.
+    pub fn owner_of<K: Borrow<T::NFTIndex>>(token_id: K) -> Option<T::AccountId> {
+        let token_id = *token_id.borrow();
+        Self::root_owner_of(token_id).filter(|_| !Self::is_expired(token_id))
+    }
+
+    /// The account at the root of the token's ownership tree.
+    fn root_owner_of(token_id: T::NFTIndex) -> Option<T::AccountId> {
+        let mut current = token_id;
+        // A token has at most `MaxNestingDepth` ancestors
+        for _ in 0..=T::MaxNestingDepth::get() {
+            match Self::token_owner(current)? {
+                Ownership::Account(owner) => return Some(owner),
+                Ownership::Token(parent) => current = parent,
+            }
+        }
+        None
+    }
+
+    /// The parents of a token, nearest first.
+    pub fn ancestors_of(token_id: T::NFTIndex) -> Vec<T::NFTIndex> {
+        let mut ancestors = Vec::new();
+        let mut current = token_id;
+        while let Some(Ownership::Token(parent)) = Self::token_owner(current) {
+            if ancestors.len() as u32 > T::MaxNestingDepth::get() {
+                break;
+            }
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// All tokens nested in a token, directly or indirectly.
+    pub fn descendants_of(token_id: T::NFTIndex) -> Vec<T::NFTIndex> {
+        Self::subtree_of(token_id).0
+    }
+
+    /// All descendants of a token, and the height of the tree below it.
+    fn subtree_of(token_id: T::NFTIndex) -> (Vec<T::NFTIndex>, u32) {
+        let mut descendants = Vec::new();
+        let mut height = 0;
+        let mut level = Self::children_of(token_id);
+        while !level.is_empty() {
+            height += 1;
+            let mut next = Vec::new();
+            for child in level {
+                next.extend(Self::children_of(child));
+                descendants.push(child);
+            }
+            level = next;
+        }
+        (descendants, height)
+    }
+
+    /// Hand the tokens nested directly in `token_id` to its root owner.
+    fn release_children(token_id: T::NFTIndex) {
+        if let Some(owner) = Self::root_owner_of(token_id) {
+            for child in <TokenChildren<T, I>>::take(token_id) {
+                <TokenOwner<T, I>>::insert(child, Ownership::Account(owner.clone()));
+                Self::deposit_event(RawEvent::Unnested(child));
+            }
+        }
+    }
+
+    /// Remove a token from the children of its parent, if it is nested.
+    fn detach(token_id: T::NFTIndex) {
+        if let Some(Ownership::Token(parent)) = Self::token_owner(token_id) {
+            <TokenChildren<T, I>>::mutate(parent, |children| children.retain(|child| *child != token_id));
+        }
+    }
+
+    /// Clear everything the previous owner granted on a token.
+    fn reset_token_state(token_id: T::NFTIndex) -> Result {
+        Self::_clear_approval(token_id)?;
+        Self::clear_user(token_id);
+        <RentalOffers<T, I>>::remove(token_id);
+        <TokenPrices<T, I>>::remove(token_id);
+        Ok(())
+    }
+
+    /// How many tokens `who` held at the end of `block`.
+    pub fn balance_of_at(who: &T::AccountId, block: T::BlockNumber) -> T::TokenCount {
+        let count = Self::balance_checkpoint_count(who);
+        Self::last_checkpoint_at(count, block, |i| Self::balance_checkpoint((who.clone(), i)).map(|c| c.0))
+            .and_then(|i| Self::balance_checkpoint((who.clone(), i)))
+            .map(|(_, balance)| balance)
+            .unwrap_or_else(Zero::zero)
+    }
+
+    /// The total supply at the end of `block`.
+    pub fn total_supply_at(block: T::BlockNumber) -> T::TokenCount {
+        let count = Self::supply_checkpoint_count();
+        Self::last_checkpoint_at(count, block, |i| Self::supply_checkpoint(i).map(|c| c.0))
+            .and_then(Self::supply_checkpoint)
+            .map(|(_, supply)| supply)
+            .unwrap_or_else(Zero::zero)
+    }
+
+    /// The root owner of a token at the end of `block`.
+    pub fn owner_of_at(token_id: T::NFTIndex, block: T::BlockNumber) -> Option<T::AccountId> {
+        let count = Self::owner_checkpoint_count(token_id);
+        Self::last_checkpoint_at(count, block, |i| Self::owner_checkpoint((token_id, i)).map(|c| c.0))
+            .and_then(|i| Self::owner_checkpoint((token_id, i)))
+            .and_then(|(_, owner)| owner)
+    }
+
+    /// Binary search for the last of `count` checkpoints recorded at or before `block`.
+    fn last_checkpoint_at<F>(count: u32, block: T::BlockNumber, block_of: F) -> Option<u32>
+        where F: Fn(u32) -> Option<T::BlockNumber>
+    {
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if block_of(mid).map_or(false, |at| at <= block) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low.checked_sub(1)
+    }
+
+    /// Update a balance and record it as of the current block.
+    fn set_balance(who: &T::AccountId, balance: T::TokenCount) {
+        <OwnedTokensCount<T, I>>::insert(who, balance);
+
+        let now = <system::Module<T>>::block_number();
+        let count = Self::balance_checkpoint_count(who);
+        // Only the last change within a block is kept
+        if let Some(last) = count.checked_sub(1) {
+            if Self::balance_checkpoint((who.clone(), last)).map_or(false, |c| c.0 == now) {
+                <BalanceCheckpoints<T, I>>::insert((who.clone(), last), (now, balance));
+                return;
+            }
+        }
+        <BalanceCheckpoints<T, I>>::insert((who.clone(), count), (now, balance));
+        <BalanceCheckpointCount<T, I>>::insert(who, count + 1);
+    }
+
+    /// Update the total supply and record it as of the current block.
+    fn set_total_supply(total_supply: T::TokenCount) {
+        <TotalSupply<T, I>>::put(total_supply);
+
+        let now = <system::Module<T>>::block_number();
+        let count = Self::supply_checkpoint_count();
+        // Only the last change within a block is kept
+        if let Some(last) = count.checked_sub(1) {
+            if Self::supply_checkpoint(last).map_or(false, |c| c.0 == now) {
+                <SupplyCheckpoints<T, I>>::insert(last, (now, total_supply));
+                return;
+            }
+        }
+        <SupplyCheckpoints<T, I>>::insert(count, (now, total_supply));
+        <SupplyCheckpointCount<I>>::put(count + 1);
+    }
+
+    /// Mint a token without role or supply cap checks. Used by `_issue_with_uri` and genesis.
+    fn do_issue(minter: &T::AccountId, who: &T::AccountId, uri: Vec<u8>) -> Result {
+        // Ids are never reused, so burning a token does not make room for a clash
+        let token_id = Self::next_token_id();
+        let next_token_id = token_id.checked_advance(1)
+            .ok_or("Overflow allocating a new token id")?;
+
+        ensure!(!<TokenOwner<T, I>>::exists(token_id), "Token hash already exists");
+        let balance_of = Self::balance_of(who);
+
+        let new_balance_of = match balance_of.checked_add(&1.into()) {
+            Some(c) => c,
+            None => return Err("Overflow adding a new token to account balance"),
+        };
+
+        T::OnNftTransfer::on_nft_transfer(None, Some(who), token_id, &[])?;
+
+        Self::supply_increase()?;
+        <NextTokenId<T, I>>::put(next_token_id);
+        <TokenUri<T, I>>::insert(token_id, uri);
+
+        <TokenOwner<T, I>>::insert(token_id, Ownership::Account(who.clone()));
+        <TokenIssuer<T, I>>::insert(token_id, minter);
+        Self::set_balance(who, new_balance_of);
+        Self::checkpoint_owner(token_id, Some(who.clone()));
+        Self::record_provenance(token_id, None, Some(who.clone()));
+        <Nonce<I>>::mutate(|n| *n += 1);
+        Self::deposit_event(RawEvent::Transfer(None, Some(who.clone()), token_id));
+
+        Ok(())
+    }
+
+    /// Append to a token's provenance, dropping the oldest records beyond `MaxProvenanceRecords`.
+    fn record_provenance(token_id: T::NFTIndex, from: Option<T::AccountId>, to: Option<T::AccountId>) {
+        let max = T::MaxProvenanceRecords::get() as usize;
+        <Provenance<T, I>>::mutate(token_id, |records| {
+            records.push(ProvenanceRecord {
+                from,
+                to,
+                block: <system::Module<T>>::block_number(),
+                price: None,
+            });
+            let excess = records.len().saturating_sub(max);
+            records.drain(..excess);
+        });
+    }
+
+    /// Set the price of the latest provenance record, for movements that were sales.
+    fn record_sale_price(token_id: T::NFTIndex, price: BalanceOf<T, I>) {
+        <Provenance<T, I>>::mutate(token_id, |records| {
+            if let Some(record) = records.last_mut() {
+                record.price = Some(price);
+            }
+        });
+    }
+
+    /// Record the root owner of a token as of the current block.
+    fn checkpoint_owner(token_id: T::NFTIndex, owner: Option<T::AccountId>) {
+        let now = <system::Module<T>>::block_number();
+        let count = Self::owner_checkpoint_count(token_id);
+        if let Some(last) = count.checked_sub(1) {
+            if Self::owner_checkpoint((token_id, last)).map_or(false, |c| c.0 == now) {
+                <OwnerCheckpoints<T, I>>::insert((token_id, last), (now, owner));
+                return;
+            }
+        }
+        <OwnerCheckpoints<T, I>>::insert((token_id, count), (now, owner));
+        <OwnerCheckpointCount<T, I>>::insert(token_id, count + 1);
+    }
+
+    /// Check an attribute against `MaxAttributeLen` and add or remove its key in `keys`.
+    /// A new key fails once the token holds `MaxAttributes` attributes of that kind.
+    fn update_attribute_keys(keys: &mut Vec<Vec<u8>>, key: &[u8], value: &Option<Vec<u8>>) -> Result {
+        let max_len = T::MaxAttributeLen::get();
+        ensure!(key.len() as u32 <= max_len, "Attribute key too long");
+
+        let known = keys.iter().any(|existing| existing[..] == *key);
+        match value {
+            Some(value) => {
+                ensure!(value.len() as u32 <= max_len, "Attribute value too long");
+                if !known {
+                    ensure!((keys.len() as u32) < T::MaxAttributes::get(), "Token has too many attributes");
+                    keys.push(key.to_vec());
+                }
+            }
+            None => keys.retain(|existing| existing[..] != *key),
+        }
+        Ok(())
+    }
+
+    /// Whether the token exists and has not expired.
+    pub fn is_valid(token_id: T::NFTIndex) -> bool {
+        <TokenOwner<T, I>>::exists(token_id) && !Self::is_expired(token_id)
+    }
+
+    fn is_expired(token_id: T::NFTIndex) -> bool {
+        Self::expires_at(token_id)
+            .map_or(false, |expires_at| expires_at <= <system::Module<T>>::block_number())
+    }
+
+    /// Queue a token to be burned at `expires_at`, replacing its previous schedule. A block's
+    /// queue holds one token less than `MaxExpiriesPerBlock`, so the sweep can clear it in one go.
+    fn schedule_expiry(token_id: T::NFTIndex, expires_at: T::BlockNumber) -> Result {
+        if Self::expires_at(token_id) == Some(expires_at) {
+            return Ok(());
+        }
+        let mut queue = Self::expiry_queue(expires_at);
+        ensure!((queue.len() as u32) + 1 < T::MaxExpiriesPerBlock::get(), "Too many tokens expire in this block");
+
+        Self::unschedule_expiry(token_id);
+        queue.push(token_id);
+        <ExpiryQueue<T, I>>::insert(expires_at, queue);
+        <TokenExpiry<T, I>>::insert(token_id, expires_at);
+        Ok(())
+    }
+
+    fn unschedule_expiry(token_id: T::NFTIndex) {
+        if let Some(expires_at) = <TokenExpiry<T, I>>::take(token_id) {
+            let mut queue = Self::expiry_queue(expires_at);
+            queue.retain(|queued| *queued != token_id);
+            if queue.is_empty() {
+                <ExpiryQueue<T, I>>::remove(expires_at);
+            } else {
+                <ExpiryQueue<T, I>>::insert(expires_at, queue);
+            }
+        }
+    }
+
+    /// Burn expired tokens, oldest first. Each queued token and each block visited
+    /// counts against `MaxExpiriesPerBlock`; leftovers are picked up in later blocks.
+    fn sweep_expired(now: T::BlockNumber) {
+        let mut budget = T::MaxExpiriesPerBlock::get();
+        let mut cursor = Self::expiry_cursor().unwrap_or(now);
+
+        while cursor <= now && budget > 0 {
+            budget -= 1;
+            let mut queue = <ExpiryQueue<T, I>>::take(cursor);
+            while budget > 0 {
+                let token_id = match queue.pop() {
+                    Some(token_id) => token_id,
+                    None => break,
+                };
+                budget -= 1;
+                // Skip tokens burned or extended since they were queued
+                if Self::expires_at(token_id).map_or(false, |expires_at| expires_at <= now) {
+                    Self::release_children(token_id);
+                    // A burn that still fails is not retried: the token stays expired and ownerless
+                    if Self::_burn(token_id).is_ok() {
+                        Self::deposit_event(RawEvent::Expired(token_id));
+                    }
+                }
+            }
+            if !queue.is_empty() {
+                <ExpiryQueue<T, I>>::insert(cursor, queue);
+                break;
+            }
+            cursor += One::one();
+        }
+
+        <ExpiryCursor<T, I>>::put(cursor);
+    }
+
+    /// Token calls are refused until storage written by an older runtime has been migrated.
+    fn ensure_migrated() -> Result {
+        let version = Self::storage_version();
+        if version == STORAGE_VERSION {
+            return Ok(());
+        }
+        // An instance added by a runtime upgrade gets no genesis, but has nothing to migrate either
+        if version == 0 && Self::total_supply().is_zero() && !<NextTokenId<T, I>>::exists() {
+            <StorageVersion<I>>::put(STORAGE_VERSION);
+            return Ok(());
+        }
+        Err("Storage migration in progress")
+    }
+
+    /// Bring storage written by an older runtime a bounded step closer to `STORAGE_VERSION`.
+    /// This Substrate has no runtime upgrade hook, and a migration may not fit in one block,
+    /// so it runs through `migrate_storage` instead of costing every block a version check.
+    fn migrate_step() -> Result {
+        let version = Self::storage_version();
+        ensure!(version < STORAGE_VERSION, "Storage is up to date");
+
+        let done = match version {
+            0 => Self::migrate_to_v1(T::MaxMigrationSteps::get()),
+            _ => true,
+        };
+        if done {
+            <StorageVersion<I>>::put(version + 1);
+            Self::deposit_event(RawEvent::StorageMigrated(version + 1));
+        }
+        Ok(())
+    }
+
+    /// Version 0 is the original ERC-721 layout: tokens are owned by and approved to bare
+    /// accounts, ids are reused after burns and balances have no history.
+    ///
+    /// Token ids can't be enumerated, but version 0 allocated them upwards from the default id,
+    /// so walk the ids until every token in the total supply has been seen, `max_steps` ids at a time.
+    /// Returns whether the walk is finished. Operator grants
+    /// can't be walked at all; owners carry them over with `migrate_operators`.
+    /// `TokenUri` needs nothing: a map of `Option<Vec<u8>>` stores the same bytes.
+    fn migrate_to_v1(max_steps: u32) -> bool {
+        let total_supply = Self::total_supply();
+        let (mut token_id, mut seen) = Self::migration_cursor()
+            .unwrap_or((T::NFTIndex::default(), T::TokenCount::zero()));
+        let mut steps = 0;
+        while seen < total_supply {
+            if steps == max_steps {
+                <MigrationCursor<T, I>>::put((token_id, seen));
+                return false;
+            }
+            steps += 1;
+            if let Some(owner) = unhashed::get::<T::AccountId>(&<TokenOwner<T, I>>::hashed_key_for(token_id)) {
+                seen += One::one();
+                <TokenOwner<T, I>>::insert(token_id, Ownership::Account(owner.clone()));
+
+                if let Some(approved) = unhashed::get::<T::AccountId>(&<TokenApprovals<T, I>>::hashed_key_for(token_id)) {
+                    <TokenApprovals<T, I>>::insert(token_id, Approval { approved, expires: None });
+                }
+
+                // Histories start at the upgrade block, so later snapshots see existing holdings
+                Self::checkpoint_owner(token_id, Some(owner.clone()));
+                if Self::balance_checkpoint_count(&owner) == 0 {
+                    Self::set_balance(&owner, Self::balance_of(&owner));
+                }
+            }
+            token_id = match token_id.checked_advance(1) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        <MigrationCursor<T, I>>::kill();
+        if Self::supply_checkpoint_count() == 0 {
+            Self::set_total_supply(total_supply);
+        }
+        // From here on, burned ids are never handed out again
+        <NextTokenId<T, I>>::put(token_id);
+        true
+    }
+
+    /// Redeemed tickets, and tokens carrying them, can no longer move.
+    /// Nor can rented tokens until the rental the user paid for ends.
+    fn ensure_transferable(token_id: T::NFTIndex, descendants: &[T::NFTIndex]) -> Result {
+        ensure!(!Self::is_redeemed(token_id), "Ticket has been redeemed");
+        ensure!(!Self::is_expired(token_id), "Token has expired");
+        ensure!(!descendants.iter().any(Self::is_redeemed), "Token carries a redeemed ticket");
+        ensure!(!Self::is_rented(token_id), "Token is rented out");
+        ensure!(!descendants.iter().any(|child| Self::is_rented(*child)), "Token carries a rented token");
+        Ok(())
+    }
+
+    /// Run the transfer hook for a token and the tokens nested in it.
+    fn notify_transfer(
+        from: Option<&T::AccountId>,
+        to: Option<&T::AccountId>,
+        token_id: T::NFTIndex,
+        descendants: &[T::NFTIndex],
+        data: &[u8],
+    ) -> Result {
+        T::OnNftTransfer::on_nft_transfer(from, to, token_id, data)?;
+        for child in descendants {
+            T::OnNftTransfer::on_nft_transfer(from, to, *child, data)?;
+        }
+        Ok(())
+    }
+
+    /// Check a resale price against the cap set by the token's issuer.
+    fn ensure_within_resale_cap(token_id: T::NFTIndex, price: BalanceOf<T, I>) -> Result {
+        if let Some(cap) = Self::resale_cap(token_id) {
+            ensure!(price <= cap, "Price exceeds resale cap");
+        }
+        Ok(())
+    }
+
+    /// The current user of the token, `None` once the user role has expired.
+    /// Whether a paid rental of the token is still running.
+    pub fn is_rented(token_id: T::NFTIndex) -> bool {
+        let now = <system::Module<T>>::block_number();
+        Self::rented_until(token_id).map_or(false, |expires| expires > now)
+    }
+
+    pub fn user_of(token_id: T::NFTIndex) -> Option<T::AccountId> {
+        let now = <system::Module<T>>::block_number();
+        Self::user_info(token_id)
+            .filter(|info| info.expires > now)
+            .map(|info| info.user)
+    }
+
+    /// The block at which the user role lapses, zero if no user is set.
+    pub fn user_expires(token_id: T::NFTIndex) -> T::BlockNumber {
+        Self::user_info(token_id)
+            .map(|info| info.expires)
+            .unwrap_or_else(Zero::zero)
+    }
+
+    /// The account approved for a single token, `None` if unset or expired.
+    pub fn get_approved<K: Borrow<T::NFTIndex>>(token_id: K) -> Option<T::AccountId> {
+        Self::token_approval(token_id)
+            .filter(|approval| Self::is_live(approval.expires))
+            .map(|approval| approval.approved)
+    }
+
+    /// The grant of `operator` over the tokens of `owner` issued by `scope`, or all of them if None.
+    fn operator_grant(owner: &T::AccountId, operator: &T::AccountId, scope: &Option<T::AccountId>) -> Option<Option<T::BlockNumber>> {
+        match scope {
+            None => Self::operator_approval((owner.clone(), operator.clone())),
+            Some(issuer) => Self::scoped_operator_approval((owner.clone(), operator.clone(), issuer.clone())),
+        }
+    }
+
+    /// Whether `operator` may manage `token_id` for `owner`, through a global or a scoped grant.
+    pub fn is_operator_for(owner: &T::AccountId, operator: &T::AccountId, token_id: T::NFTIndex) -> bool {
+        let is_live_grant = |scope| Self::operator_grant(owner, operator, &scope).map(Self::is_live).unwrap_or(false);
+        is_live_grant(None)
+            || Self::is_legacy_operator(owner, operator)
+            || Self::issuer_of(token_id).map_or(false, |issuer| is_live_grant(Some(issuer)))
+    }
+
+    /// Whether `operator` holds a grant made before storage version 1 that has been
+    /// neither migrated, replaced nor revoked.
+    fn is_legacy_operator(owner: &T::AccountId, operator: &T::AccountId) -> bool {
+        !Self::legacy_operators_revoked(owner) && <OperatorApprovals<T, I>>::get((owner.clone(), operator.clone()))
+    }
+
+    /// Grant or revoke an operator, keeping the owner's operator list in step.
+    fn set_operator(
+        owner: &T::AccountId,
+        operator: &T::AccountId,
+        scope: Option<T::AccountId>,
+        approved: bool,
+        expires: Option<T::BlockNumber>,
+    ) -> Result {
+        ensure!(operator != owner, "You can not set approval for yourself!");
+
+        // A new global grant, or its revocation, supersedes any grant made before storage version 1
+        if scope.is_none() {
+            <OperatorApprovals<T, I>>::remove((owner.clone(), operator.clone()));
+        }
+        if approved {
+            ensure!(Self::is_live(expires), "Approval expiry must be in the future");
+            Self::remove_expired_operators(owner);
+            let grant = (operator.clone(), scope.clone());
+            let mut listed = Self::operators_of(owner);
+            if !listed.contains(&grant) {
+                ensure!((listed.len() as u32) < T::MaxOperators::get(), "Too many operators");
+                listed.push(grant);
+                <Operators<T, I>>::insert(owner, listed);
+            }
+            match scope {
+                None => <OperatorGrants<T, I>>::insert((owner.clone(), operator.clone()), expires),
+                Some(issuer) => <ScopedOperatorApprovals<T, I>>::insert((owner.clone(), operator.clone(), issuer), expires),
+            }
+        } else {
+            Self::remove_operator(owner, operator, scope);
+        }
+        Ok(())
+    }
+
+    /// Drop the owner's lapsed grants so they no longer count against `MaxOperators`.
+    fn remove_expired_operators(owner: &T::AccountId) {
+        for (operator, scope) in Self::operators_of(owner) {
+            if Self::operator_grant(owner, &operator, &scope).map_or(true, |expires| !Self::is_live(expires)) {
+                Self::remove_operator(owner, &operator, scope);
+            }
+        }
+    }
+
+    fn remove_operator(owner: &T::AccountId, operator: &T::AccountId, scope: Option<T::AccountId>) {
+        <Operators<T, I>>::mutate(owner, |listed| listed.retain(|grant| grant.0 != *operator || grant.1 != scope));
+        match scope {
+            None => <OperatorGrants<T, I>>::remove((owner.clone(), operator.clone())),
+            Some(issuer) => <ScopedOperatorApprovals<T, I>>::remove((owner.clone(), operator.clone(), issuer)),
+        }
+    }
+
+    /// Whether `operator` may manage all tokens of `owner`. Expired grants count as revoked.
+    pub fn is_approved_for_all(key: (T::AccountId, T::AccountId)) -> bool {
+        Self::is_legacy_operator(&key.0, &key.1) || Self::operator_approval(key)
+            .map(Self::is_live)
+            .unwrap_or(false)
+    }
+
+    fn is_live(expires: Option<T::BlockNumber>) -> bool {
+        expires.map_or(true, |expires| expires > <system::Module<T>>::block_number())
+    }
+
+    /*************************************************
+    Function:       // do_approve设置可过期的普通授权
+    Description:    // 与_approve相同，expires为None表示永不过期，到期后授权自动失效
+    Input:
+                    origin  设置授权用户ID，须为代币拥有者或其高级授权用户
+                    to      接收授权用户ID
+                    token_id NFT代币的下标
+                    expires  授权到期区块
+    Output:
+    Return:         Result    执行结果
+    *************************************************/
+    fn do_approve(origin: T::AccountId, to: T::AccountId, token_id: T::NFTIndex, expires: Option<T::BlockNumber>) -> Result {
+        let owner = Self::owner_of(token_id).ok_or("No owner for this token")?;
+
+        // check msg sender
+        ensure!(owner == origin || Self::is_operator_for(&owner, &origin, token_id),
+            "You can not approve the token,Because You did not own it!");
+        ensure!(to != owner, "You can not set approval for yourself!");
+        ensure!(Self::is_live(expires), "Approval expiry must be in the future");
+
+        // Set approved state
+        <TokenApprovals<T, I>>::insert(token_id, Approval { approved: to.clone(), expires });
+
+        Self::deposit_event(RawEvent::Approval(owner, to, token_id, expires));
+        Ok(())
+    }
+
+    /*************************************************
+    Function:       // do_set_approval_for_all设置可过期的高级授权
+    Description:    // 与_set_approval_for_all相同，expires为None表示永不过期，到期后授权自动失效
+    Input:
+                    origin  设置授权用户ID
+                    to      接收授权用户ID
+                    approved 设置授权标识,true为允许
+                    expires  授权到期区块
+    Output:
+    Return:         Result    执行结果
+    *************************************************/
+    fn do_set_approval_for_all(origin: T::AccountId, to: T::AccountId, approved: bool, expires: Option<T::BlockNumber>) -> Result {
+        Self::set_operator(&origin, &to, None, approved, expires)?;
+
+        Self::deposit_event(RawEvent::ApprovalForAll(origin, to, approved, expires));
+        Ok(())
+    }
+
+    /// Whether `spender` may act on the token. Expired approvals count as revoked.
+    fn _is_approved_or_owner(spender: &T::AccountId, token_id: T::NFTIndex) -> bool {
+        match Self::owner_of(token_id) {
+            Some(owner) => *spender == owner
+                || Self::get_approved(token_id).as_ref() == Some(spender)
+                || Self::is_operator_for(&owner, spender, token_id),
+            None => false,
+        }
+    }
+
+    fn do_set_user(token_id: T::NFTIndex, user: T::AccountId, expires: T::BlockNumber) {
+        <TokenUsers<T, I>>::insert(token_id, UserInfo { user: user.clone(), expires });
+        Self::deposit_event(RawEvent::UpdateUser(token_id, Some(user), expires));
+    }
+
+    fn clear_user(token_id: T::NFTIndex) {
+        <RentedUntil<T, I>>::remove(token_id);
+        if <TokenUsers<T, I>>::exists(token_id) {
+            <TokenUsers<T, I>>::remove(token_id);
+            Self::deposit_event(RawEvent::UpdateUser(token_id, None, Zero::zero()));
+        }
+    }
+}
+
+
+decl_event!(
+	pub enum Event<T, I: Instance = DefaultInstance> where
+		<T as system::Trait>::AccountId,
+		<T as Trait<I>>::NFTIndex,
+		<T as Trait<I>>::TokenCount,
+		<T as system::Trait>::BlockNumber,
+		<T as system::Trait>::Hash,
+		Balance = BalanceOf<T, I>,
+	{
+		//转账事件
+        Transfer(Option<AccountId>, Option<AccountId>, NFTIndex),
+		//携带附加数据（如订单号）的转账事件，在Transfer之后发出 (from, to, token_id, data)
+        TransferWithData(AccountId, AccountId, NFTIndex, Vec<u8>),
+		//普通授权事件 (owner, approved, token_id, expires)
+        Approval(AccountId, AccountId, NFTIndex, Option<BlockNumber>),
+		//高级授权事件 (owner, operator, approved, expires)
+        ApprovalForAll(AccountId, AccountId, bool, Option<BlockNumber>),
+		//限定范围的高级授权事件 (owner, operator, issuer, approved, expires)
+        ScopedApprovalForAll(AccountId, AccountId, AccountId, bool, Option<BlockNumber>),
+		//撤销全部高级授权事件 (owner)
+        OperatorsRevoked(AccountId),
+		//发行者角色变更事件
+		IssuerSet(AccountId, bool),
+		//铸造者角色变更事件
+		MinterSet(AccountId, bool),
+		//最大供应量变更事件
+		MaxSupplySet(Option<TokenCount>),
+		//代币凭证兑换事件 (creator, redeemer, token_id, price)
+		VoucherRedeemed(AccountId, AccountId, NFTIndex, Balance),
+		//白名单发行配置事件 (issuer, root)
+		AllowlistSet(AccountId, Option<Hash>),
+		//白名单领取事件 (account, token_id)
+		AllowlistClaimed(AccountId, NFTIndex),
+		//发售配置事件 (issuer, sale_id)
+		SaleSet(AccountId, u32),
+		//发售关闭事件 (issuer, sale_id)
+		SaleClosed(AccountId, u32),
+		//发售购买事件 (buyer, token_id, price)
+		SaleMinted(AccountId, NFTIndex, Balance),
+		//隐藏元数据批次发行事件 (issuer, batch_id, first, count)
+		HiddenBatchIssued(AccountId, u32, NFTIndex, u32),
+		//元数据揭示事件 (batch_id)
+		BatchRevealed(u32),
+		//有效期变更事件 (token_id, expires_at)
+		ExpirySet(NFTIndex, Option<BlockNumber>),
+		//延长有效期策略变更事件 (issuer, max_extension)
+		ExtensionPolicySet(AccountId, Option<BlockNumber>),
+		//代币到期销毁事件
+		Expired(NFTIndex),
+		//属性变更事件 (owner, token_id, key, value)
+		AttributeSet(AccountId, NFTIndex, Vec<u8>, Option<Vec<u8>>),
+		//控制者变更事件 (issuer, controller, enabled)
+		ControllerSet(AccountId, AccountId, bool),
+		//保留属性变更事件 (controller, token_id, key, value)
+		ControlledAttributeSet(AccountId, NFTIndex, Vec<u8>, Option<Vec<u8>>),
+		//核销员变更事件 (issuer, verifier, enabled)
+		VerifierSet(AccountId, AccountId, bool),
+		//门票核销事件 (verifier, owner, token_id)
+		TicketRedeemed(AccountId, AccountId, NFTIndex),
+		//最高转售价格变更事件 (token_id, cap)
+		ResaleCapSet(NFTIndex, Option<Balance>),
+		//出售报价事件 (owner, token_id, price)
+		Ask(AccountId, NFTIndex, Option<Balance>),
+		//出售成交事件 (from, to, token_id, price)
+		Sold(AccountId, AccountId, NFTIndex, Balance),
+		//代币嵌套事件 (token_id, parent)
+		Nested(NFTIndex, NFTIndex),
+		//代币取出嵌套事件 (token_id)
+		Unnested(NFTIndex),
+		//使用者变更事件 (token_id, user, expires)
+		UpdateUser(NFTIndex, Option<AccountId>, BlockNumber),
+		//出租报价事件 (owner, token_id, price_per_block, max_duration)
+		RentAsk(AccountId, NFTIndex, Option<Balance>, BlockNumber),
+		//出租成交事件 (owner, renter, token_id, expires, cost)
+		Rented(AccountId, AccountId, NFTIndex, BlockNumber, Balance),
+		//存储迁移事件，参数为迁移后的版本
+		StorageMigrated(u32),
+	}
+);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use runtime_io::with_externalities;
+    use primitives::{H256, U256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types, traits::Currency};
+    use sr_primitives::{traits::{BlakeTwo256, IdentityLookup, Lazy, OnInitialize}, testing::Header};
+    use sr_primitives::weights::Weight;
+    use sr_primitives::Perbill;
+    use std::cell::RefCell;
+
+    impl_outer_origin! {
+            pub enum Origin for Test {}
+    }
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    pub struct Test;
+    parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+    }
+    impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type WeightMultiplierUpdate = ();
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
+            type MaximumBlockLength = MaximumBlockLength;
+            type AvailableBlockRatio = AvailableBlockRatio;
+            type Version = ();
+    }
+    parameter_types! {
+            pub const ExistentialDeposit: u64 = 0;
+            pub const TransferFee: u64 = 0;
+            pub const CreationFee: u64 = 0;
+            pub const TransactionBaseFee: u64 = 0;
+            pub const TransactionByteFee: u64 = 0;
+    }
+    impl balances::Trait for Test {
+            type Balance = u64;
+            type OnFreeBalanceZero = ();
+            type OnNewAccount = ();
+            type Event = ();
+            type TransactionPayment = ();
+            type TransferPayment = ();
+            type DustRemoval = ();
+            type ExistentialDeposit = ExistentialDeposit;
+            type TransferFee = TransferFee;
+            type CreationFee = CreationFee;
+            type TransactionBaseFee = TransactionBaseFee;
+            type TransactionByteFee = TransactionByteFee;
+            type WeightToFee = ();
+    }
+    parameter_types! {
+            pub const MaxNestingDepth: u32 = 3;
+            pub const MaxExpiriesPerBlock: u32 = 3;
+            pub const MaxTransferDataLen: u32 = 8;
+            pub const MaxOperators: u32 = 2;
+            pub const MaxProvenanceRecords: u32 = 3;
+            pub const MaxChildren: u32 = 2;
+            pub const MaxBatchSize: u32 = 4;
+            pub const MaxAttributeLen: u32 = 8;
+            pub const MaxAttributes: u32 = 2;
+            pub const MaxMigrationSteps: u32 = 2;
+    }
+    impl Trait for Test {
+            type NFTIndex = u128;
+            type TokenCount = u128;
+            type Event = ();
+            type Currency = balances::Module<Test>;
+            type MaxNestingDepth = MaxNestingDepth;
+            type AdminOrigin = system::EnsureRoot<u64>;
+            type Signature = TestSignature;
+            type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+            type OnNftTransfer = TestHook;
+            type Escrow = ();
+            type OnControlledAttributeChange = ();
+            type MaxTransferDataLen = MaxTransferDataLen;
+            type MaxOperators = MaxOperators;
+            type MaxProvenanceRecords = MaxProvenanceRecords;
+            type MaxChildren = MaxChildren;
+            type MaxBatchSize = MaxBatchSize;
+            type MaxAttributeLen = MaxAttributeLen;
+            type MaxAttributes = MaxAttributes;
+            type MaxMigrationSteps = MaxMigrationSteps;
+    }
+    impl Trait<Instance1> for Test {
+            type NFTIndex = U256;
+            type TokenCount = u32;
+            type Event = ();
+            type Currency = balances::Module<Test>;
+            type MaxNestingDepth = MaxNestingDepth;
+            type AdminOrigin = system::EnsureRoot<u64>;
+            type Signature = TestSignature;
+            type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+            type OnNftTransfer = ();
+            type Escrow = ();
+            type OnControlledAttributeChange = ();
+            type MaxTransferDataLen = MaxTransferDataLen;
+            type MaxOperators = MaxOperators;
+            type MaxProvenanceRecords = MaxProvenanceRecords;
+            type MaxChildren = MaxChildren;
+            type MaxBatchSize = MaxBatchSize;
+            type MaxAttributeLen = MaxAttributeLen;
+            type MaxAttributes = MaxAttributes;
+            type MaxMigrationSteps = MaxMigrationSteps;
+    }
+
+    thread_local! {
+        static MOVES: RefCell<Vec<(Option<u64>, Option<u64>, u128, Vec<u8>)>> = RefCell::new(Vec::new());
+    }
+
+    /// Records every token movement and refuses to send tokens to account 99.
+    pub struct TestHook;
+
+    impl OnNftTransfer<u64, u128> for TestHook {
+            fn on_nft_transfer(from: Option<&u64>, to: Option<&u64>, token_id: u128, data: &[u8]) -> Result {
+                    ensure!(to != Some(&99), "Account 99 does not accept tokens");
+                    MOVES.with(|moves| moves.borrow_mut().push((from.cloned(), to.cloned(), token_id, data.to_vec())));
+                    Ok(())
+            }
+    }
+
+    /// A signature that is valid for exactly the signer and message it carries.
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
+    pub struct TestSignature(pub u64, pub Vec<u8>);
+
+    impl Verify for TestSignature {
+            type Signer = u64;
+            fn verify<L: Lazy<[u8]>>(&self, mut msg: L, signer: &u64) -> bool {
+                    *signer == self.0 && msg.get() == &self.1[..]
+            }
+    }
+    type TestModule = Module<Test>;
+    type TicketModule = Module<Test, Instance1>;
+    type Balances = balances::Module<Test>;
+    type System = system::Module<Test>;
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup.
+    // Account 0 holds the issuer role.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            GenesisConfig::<Test>::default().assimilate_storage(&mut t).unwrap();
+            GenesisConfig::<Test, Instance1>::default().assimilate_storage(&mut t).unwrap();
+            let mut ext: runtime_io::TestExternalities<Blake2Hasher> = t.into();
+            with_externalities(&mut ext, || <Issuers<Test>>::insert(0, true));
+            ext
+    }
+
+    #[test]
+    fn test_genesis_config() {
+        let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+        GenesisConfig::<Test> {
+            tokens: vec![(0, b"ipfs://a".to_vec()), (1, b"ipfs://b".to_vec()), (0, b"ipfs://c".to_vec())],
+            operators: vec![(0, 2)],
+            issuers: vec![3],
+        }.assimilate_storage(&mut t).unwrap();
+
+        let mut ext: runtime_io::TestExternalities<Blake2Hasher> = t.into();
+        with_externalities(&mut ext, || {
+            assert_eq!(TestModule::total_supply(), 3);
+            assert_eq!(TestModule::next_token_id(), 3);
+            assert_eq!(TestModule::balance_of(&0), 2);
+            assert_eq!(TestModule::balance_of(&1), 1);
+            assert_eq!(TestModule::owner_of(1), Some(1));
+            assert_eq!(TestModule::token_uri(2), b"ipfs://c".to_vec());
+            assert!(TestModule::is_approved_for_all((0, 2)));
+            assert_eq!(TestModule::operators_of(0), vec![(2, None)]);
+            assert_eq!(TestModule::storage_version(), STORAGE_VERSION);
+            assert!(TestModule::is_issuer(3));
+            assert!(!TestModule::is_issuer(0));
+        });
+    }
+    #[test]
+    fn test_storage_migration() {
+        let mut ext: runtime_io::TestExternalities<Blake2Hasher> =
+            system::GenesisConfig::default().build_storage::<Test>().unwrap().into();
+        with_externalities(&mut ext, || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+
+            // Version 0 layout: tokens 0 and 2 held by bare accounts, token 1 burned
+            unhashed::put(&<TokenOwner<Test>>::hashed_key_for(0), &alice);
+            unhashed::put(&<TokenOwner<Test>>::hashed_key_for(2), &bob);
+            unhashed::put(&<TokenApprovals<Test>>::hashed_key_for(2), &charlie);
+            <OwnedTokensCount<Test>>::insert(alice, 1);
+            <OwnedTokensCount<Test>>::insert(bob, 1);
+            <TotalSupply<Test>>::put(2);
+            <OperatorApprovals<Test>>::insert((alice, charlie), true);
+            <OperatorApprovals<Test>>::insert((bob, charlie), false);
+
+            System::set_block_number(5);
+            assert_noop!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()),
+                "Storage migration in progress");
+            // Two ids per call: 0 and 1, then 2
+            assert_ok!(TestModule::migrate_storage(Origin::signed(charlie)));
+            assert_eq!(TestModule::storage_version(), 0);
+            assert_eq!(TestModule::migration_cursor(), Some((2, 1)));
+            assert_noop!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]), "Storage migration in progress");
+            assert_ok!(TestModule::migrate_storage(Origin::signed(charlie)));
+            assert_eq!(TestModule::storage_version(), STORAGE_VERSION);
+            assert_eq!(TestModule::migration_cursor(), None);
+            assert_eq!(TestModule::owner_of(0), Some(alice));
+            assert_eq!(TestModule::owner_of(1), None);
+            assert_eq!(TestModule::owner_of(2), Some(bob));
+            assert_eq!(TestModule::get_approved(2), Some(charlie));
+            assert_eq!(TestModule::next_token_id(), 3);
+            assert_eq!(TestModule::balance_of_at(&bob, 5), 1);
+            assert_eq!(TestModule::owner_of_at(0, 5), Some(alice));
+
+            // Operator grants keep working until they are carried over, and revoked ones stay revoked
+            assert!(TestModule::is_approved_for_all((alice, charlie)));
+            assert!(TestModule::_is_approved_or_owner(&charlie, 0));
+            assert!(!TestModule::is_approved_for_all((bob, charlie)));
+            assert_ok!(TestModule::migrate_operators(Origin::signed(alice), vec![charlie]));
+            assert_ok!(TestModule::migrate_operators(Origin::signed(bob), vec![charlie]));
+            assert!(TestModule::is_approved_for_all((alice, charlie)));
+            assert!(!TestModule::is_approved_for_all((bob, charlie)));
+            assert_eq!(TestModule::operators_of(alice), vec![(charlie, None)]);
+            assert_eq!(TestModule::operators_of(bob), vec![]);
+
+            // A new grant or revocation replaces an unmigrated one
+            <OperatorApprovals<Test>>::insert((bob, alice), true);
+            assert_ok!(TestModule::set_approval_for_all(Origin::signed(bob), alice, false));
+            assert!(!TestModule::is_approved_for_all((bob, alice)));
+
+            // Migrated storage is left alone afterwards
+            assert_noop!(TestModule::migrate_storage(Origin::signed(charlie)), "Storage is up to date");
+            assert_eq!(TestModule::owner_checkpoint_count(0), 1);
+        });
+    }
+    #[test]
+    fn test_instance_added_by_upgrade() {
+        let mut ext: runtime_io::TestExternalities<Blake2Hasher> =
+            system::GenesisConfig::default().build_storage::<Test>().unwrap().into();
+        with_externalities(&mut ext, || {
+            let alice = 0;
+            // No genesis ran for this instance, and it holds no tokens to migrate
+            assert_eq!(TicketModule::storage_version(), 0);
+            assert_ok!(TicketModule::set_issuer(Origin::ROOT, alice, true));
+            assert_eq!(TicketModule::storage_version(), STORAGE_VERSION);
+            assert_ok!(TicketModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_noop!(TicketModule::migrate_storage(Origin::signed(alice)), "Storage is up to date");
+        });
+    }
+    #[test]
+    fn test_issue() {
+        with_externalities(&mut new_test_ext(), || {
+        });
+    }
+    #[test]
+    fn test_burn() {
+        with_externalities(&mut new_test_ext(), || {
+        });
+    }
+    #[test]
+    fn test_approve() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let new_tk_id = TestModule::total_supply();
+            TestModule::issue_with_uri(Origin::signed(alice),b"https://this_is_a_test.com".to_vec());
+            assert_eq!(TestModule::balance_of(&alice), 1);
+            // TestModule::approve(Origin::signed(alice), alice, new_tk_id);
+            // assert_eq!(TestModule::get_approved(&new_tk_id), Some(alice));
+            assert_eq!(TestModule::total_supply(), 1);
+            TestModule::approve(Origin::signed(alice), bob, new_tk_id);
+            assert_eq!(TestModule::get_approved(&new_tk_id), Some(bob));
+
+        });
+    }
+    #[test]
+    fn test_transfer_from() {
+        with_externalities(&mut new_test_ext(), || {
+        });
+    }
+    #[test]
+    fn test_set_approval_for_all() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            // let new_tk_id = TestModule::total_supply();
+            // TestModule::issue_with_uri(Origin::signed(alice),b"https://this_is_a_test.com".to_vec());
+            // assert_eq!(TestModule::balance_of(&alice), 1);
+            // assert_eq!(TestModule::total_supply(), 1);
+            TestModule::set_approval_for_all(Origin::signed(alice), bob, true);
+            assert_eq!(TestModule::is_approved_for_all((alice,bob)), true);
+        });
+    }
+    #[test]
+    fn test_expiring_approvals() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let dave = 3;
+            System::set_block_number(1);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+
+            assert_noop!(TestModule::approve_until(Origin::signed(alice), bob, 0, 1), "Approval expiry must be in the future");
+            assert_ok!(TestModule::approve_until(Origin::signed(alice), bob, 0, 5));
+            assert_ok!(TestModule::set_approval_for_all_until(Origin::signed(alice), charlie, 3));
+            assert_eq!(TestModule::get_approved(0), Some(bob));
+            assert!(TestModule::is_approved_for_all((alice, charlie)));
+            assert!(TestModule::_is_approved_or_owner(&charlie, 0));
+
+            System::set_block_number(5);
+            assert_eq!(TestModule::get_approved(0), None);
+            assert!(!TestModule::is_approved_for_all((alice, charlie)));
+            assert!(!TestModule::_is_approved_or_owner(&bob, 0));
+            assert!(!TestModule::_is_approved_or_owner(&charlie, 0));
+            // Checking a lapsed grant leaves storage alone
+            assert_eq!(TestModule::operator_approval((alice, charlie)), Some(Some(3)));
+
+            // Lapsed grants are dropped the next time the owner grants an operator
+            assert_ok!(TestModule::set_approval_for_all(Origin::signed(alice), dave, true));
+            assert_eq!(TestModule::operator_approval((alice, charlie)), None);
+            assert_eq!(TestModule::operators_of(alice), vec![(dave, None)]);
+        });
+    }
+    #[test]
+    fn test_scoped_operators() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let dave = 3;
+            assert_ok!(TestModule::set_minter(Origin::ROOT, bob, true));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(bob), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(bob, alice, 1, vec![]));
+
+            // Charlie may only manage alice's tokens issued by bob
+            assert_ok!(TestModule::set_scoped_approval_for_all(Origin::signed(alice), charlie, bob, true, None));
+            assert!(!TestModule::_is_approved_or_owner(&charlie, 0));
+            assert!(TestModule::_is_approved_or_owner(&charlie, 1));
+
+            assert_ok!(TestModule::set_approval_for_all(Origin::signed(alice), dave, true));
+            assert_noop!(TestModule::set_approval_for_all(Origin::signed(alice), bob, true), "Too many operators");
+            assert_eq!(TestModule::operators_of(alice), vec![(charlie, Some(bob)), (dave, None)]);
+
+            // Grants made before storage version 1
+            <OperatorApprovals<Test>>::insert((alice, dave), true);
+            <OperatorApprovals<Test>>::insert((bob, dave), true);
+            assert!(TestModule::is_approved_for_all((bob, dave)));
+            assert_ok!(TestModule::migrate_operators(Origin::signed(bob), vec![charlie, dave, dave]));
+            assert_eq!(TestModule::operators_of(bob), vec![(dave, None)]);
+            assert!(TestModule::is_approved_for_all((bob, dave)));
+
+            assert_ok!(TestModule::revoke_all_operators(Origin::signed(alice)));
+            assert_eq!(TestModule::operators_of(alice), vec![]);
+            assert!(!TestModule::is_approved_for_all((alice, dave)));
+            assert_eq!(TestModule::scoped_operator_approval((alice, charlie, bob)), None);
+            // Unmigrated grants are revoked too
+            assert_noop!(TestModule::migrate_operators(Origin::signed(alice), vec![dave]), "Legacy operators were revoked");
+            assert!(!TestModule::is_approved_for_all((alice, dave)));
+        });
+    }
+    #[test]
+    fn test_set_user_lapses() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            System::set_block_number(1);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+
+            assert_noop!(TestModule::set_user(Origin::signed(bob), 0, bob, 10), "Only owner or approved account can set user");
+            assert_ok!(TestModule::set_user(Origin::signed(alice), 0, bob, 10));
+            assert_eq!(TestModule::user_of(0), Some(bob));
+            assert_eq!(TestModule::user_expires(0), 10);
+
+            System::set_block_number(10);
+            assert_eq!(TestModule::user_of(0), None);
+            assert_eq!(TestModule::owner_of(0), Some(alice));
+        });
+    }
+    #[test]
+    fn test_transfer_clears_user() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::set_user(Origin::signed(alice), 0, bob, 10));
+
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, charlie, 0, vec![]));
+            assert_eq!(TestModule::user_of(0), None);
+        });
+    }
+    #[test]
+    fn test_rent() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            System::set_block_number(1);
+            let _ = Balances::deposit_creating(&bob, 100);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+
+            assert_noop!(TestModule::rent(Origin::signed(bob), 0, 5, 3), "Token not for rent");
+            assert_ok!(TestModule::ask_rent(Origin::signed(alice), 0, Some(3), 20));
+            assert_noop!(TestModule::rent(Origin::signed(bob), 0, 21, 3), "Rental duration is too long");
+            assert_noop!(TestModule::rent(Origin::signed(bob), 0, 5, 2), "Price is too low");
+
+            assert_ok!(TestModule::rent(Origin::signed(bob), 0, 5, 3));
+            assert_eq!(Balances::free_balance(&bob), 85);
+            assert_eq!(Balances::free_balance(&alice), 15);
+            assert_eq!(TestModule::user_of(0), Some(bob));
+            assert_eq!(TestModule::user_expires(0), 6);
+
+            // The owner can't take the token back from a paying user before the rental ends
+            assert_noop!(<TestModule as NFTS<_, _>>::transfer_from(alice, charlie, 0, vec![]), "Token is rented out");
+            assert_noop!(TestModule::burn(Origin::signed(alice), 0), "Token is rented out");
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_noop!(TestModule::nest(Origin::signed(alice), 0, 1), "Token is rented out");
+            assert_noop!(TestModule::set_expiry(Origin::signed(alice), 0, Some(5)), "Expiry can only be extended once the token is sold or rented");
+
+            System::set_block_number(6);
+            assert_eq!(TestModule::user_of(0), None);
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, charlie, 0, vec![]));
+            assert_eq!(TestModule::rented_until(0), None);
+
+            // A rental can't run past the token's expiry
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 1, Some(10)));
+            assert_ok!(TestModule::ask_rent(Origin::signed(alice), 1, Some(3), 20));
+            assert_noop!(TestModule::rent(Origin::signed(bob), 1, 5, 3), "Rental outlasts the token");
+            assert_ok!(TestModule::rent(Origin::signed(bob), 1, 4, 3));
+        });
+    }
+    #[test]
+    fn test_nested_tokens() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            for _ in 0..3 {
+                assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            }
+
+            assert_ok!(TestModule::nest(Origin::signed(alice), 1, 0));
+            assert_ok!(TestModule::nest(Origin::signed(alice), 2, 1));
+            assert_noop!(TestModule::nest(Origin::signed(alice), 0, 2), "Nesting would create a cycle");
+            assert_eq!(TestModule::owner_of(2), Some(alice));
+            assert_eq!(TestModule::descendants_of(0), vec![1, 2]);
+
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]));
+            assert_eq!(TestModule::owner_of(2), Some(bob));
+            assert_eq!(TestModule::balance_of(&alice), 0);
+            assert_eq!(TestModule::balance_of(&bob), 3);
+
+            assert_noop!(TestModule::unnest(Origin::signed(alice), 1), "Only owner or approved account can unnest token");
+            assert_noop!(TestModule::unnest(Origin::signed(bob), 0), "Token is not nested");
+            assert_ok!(TestModule::unnest(Origin::signed(bob), 1));
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(bob, alice, 1, vec![]));
+            assert_eq!(TestModule::children_of(0), Vec::<u128>::new());
+            assert_eq!(TestModule::owner_of(2), Some(alice));
+            assert_eq!(TestModule::balance_of(&alice), 2);
+            assert_eq!(TestModule::balance_of(&bob), 1);
+
+            // Nesting into someone else's token needs their approval, and breadth is capped
+            assert_noop!(TestModule::nest(Origin::signed(alice), 1, 0), "Only owner or approved account can nest into parent");
+            assert_ok!(TestModule::approve(Origin::signed(bob), alice, 0));
+            assert_ok!(TestModule::nest(Origin::signed(alice), 1, 0));
+            for _ in 0..2 {
+                assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            }
+            assert_ok!(TestModule::nest(Origin::signed(alice), 3, 0));
+            assert_noop!(TestModule::nest(Origin::signed(alice), 4, 0), "Parent has too many children");
+            assert_eq!(TestModule::children_of(0), vec![1, 3]);
+        });
+    }
+    #[test]
+    fn test_minter_roles_and_max_supply() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            assert_noop!(TestModule::issue_with_uri(Origin::signed(bob), b"https://this_is_a_test.com".to_vec()),
+                "Only issuer or minter can issue token");
+            assert_noop!(TestModule::set_minter(Origin::signed(charlie), bob, true), "Only admin or issuer can manage minting");
+            assert!(TestModule::set_issuer(Origin::signed(alice), bob, true).is_err());
+
+            assert_ok!(TestModule::set_minter(Origin::signed(alice), bob, true));
+            assert!(TestModule::set_max_supply(Origin::signed(alice), None).is_err());
+            assert_ok!(TestModule::set_max_supply(Origin::ROOT, Some(2)));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(bob), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_noop!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()),
+                "Token supply cap reached");
+
+            // Another issuer can't touch alice's minters, and they lapse with her role
+            assert_ok!(TestModule::set_issuer(Origin::ROOT, charlie, true));
+            assert_noop!(TestModule::set_minter(Origin::signed(charlie), bob, false), "Minter was granted by another account");
+            assert_ok!(TestModule::set_issuer(Origin::ROOT, alice, false));
+            assert!(!TestModule::can_mint(&bob));
+            assert_ok!(TestModule::set_issuer(Origin::ROOT, alice, true));
+            assert!(TestModule::can_mint(&bob));
+
+            assert_ok!(TestModule::set_minter(Origin::signed(alice), bob, false));
+            assert!(!TestModule::can_mint(&bob));
+            assert_ok!(TestModule::set_minter(Origin::ROOT, bob, true));
+            assert_noop!(TestModule::set_minter(Origin::signed(alice), bob, false), "Minter was granted by another account");
+            assert_ok!(TestModule::set_minter(Origin::ROOT, bob, false));
+            assert!(!TestModule::can_mint(&bob));
+        });
+    }
+    #[test]
+    fn test_redeem_voucher() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let _ = Balances::deposit_creating(&alice, 1);
+            let _ = Balances::deposit_creating(&bob, 100);
+            let uri = b"https://this_is_a_test.com".to_vec();
+
+            let payload = TestModule::voucher_payload(&uri, &30, 7, &Some(bob));
+            let signature = TestSignature(alice, payload);
+
+            assert_noop!(TestModule::redeem_voucher(Origin::signed(bob), alice, uri.clone(), 20, 7, Some(bob), signature.clone()),
+                "Invalid voucher signature");
+            assert_noop!(TestModule::redeem_voucher(Origin::signed(charlie), alice, uri.clone(), 30, 7, Some(bob), signature.clone()),
+                "Voucher is reserved for another account");
+
+            assert_ok!(TestModule::redeem_voucher(Origin::signed(bob), alice, uri.clone(), 30, 7, Some(bob), signature.clone()));
+            assert_eq!(TestModule::owner_of(0), Some(bob));
+            assert_eq!(TestModule::token_uri(0), uri);
+            assert_eq!(Balances::free_balance(&alice), 31);
+            assert_eq!(Balances::reserved_balance(&bob), 0);
+
+            assert_noop!(TestModule::redeem_voucher(Origin::signed(bob), alice, uri.clone(), 30, 7, Some(bob), signature.clone()),
+                "Voucher already redeemed");
+
+            // The signature is bound to this registry
+            <Issuers<Test, Instance1>>::insert(alice, true);
+            assert_noop!(TicketModule::redeem_voucher(Origin::signed(bob), alice, uri.clone(), 30, 7, Some(bob), signature),
+                "Invalid voucher signature");
+
+            // A mint that fails takes no payment
+            let _ = Balances::deposit_creating(&99, 100);
+            let signature = TestSignature(alice, TestModule::voucher_payload(&uri, &30, 8, &None));
+            assert_noop!(TestModule::redeem_voucher(Origin::signed(99), alice, uri, 30, 8, None, signature),
+                "Account 99 does not accept tokens");
+            assert_eq!(Balances::free_balance(&99), 100);
+            assert_eq!(Balances::reserved_balance(&99), 0);
+        });
+    }
+    #[test]
+    fn test_permit() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+
+            let payload = TestModule::permit_payload(&alice, &bob, 0, 0, 10);
+            let signature = TestSignature(alice, payload);
+
+            assert_noop!(TestModule::permit(Origin::signed(charlie), alice, charlie, 0, 10, signature.clone()),
+                "Invalid permit signature");
+            // Anyone can relay the permit
+            assert_ok!(TestModule::permit(Origin::signed(charlie), alice, bob, 0, 10, signature.clone()));
+            assert_eq!(TestModule::get_approved(&0), Some(bob));
+            assert_eq!(TestModule::permit_nonce(&alice), 1);
+            assert_noop!(TestModule::permit(Origin::signed(charlie), alice, bob, 0, 10, signature),
+                "Invalid permit signature");
+
+            // Signatures are bound to the registry they were made for
+            assert_ne!(TestModule::signing_domain(), TicketModule::signing_domain());
+
+            let payload = TestModule::permit_payload(&alice, &charlie, 0, 1, 10);
+            System::set_block_number(11);
+            assert_noop!(TestModule::permit(Origin::signed(charlie), alice, charlie, 0, 10, TestSignature(alice, payload)),
+                "Permit has expired");
+        });
+    }
+    #[test]
+    fn test_allowlist_mint() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let dave = 3;
+            let eve = 4;
+            let leaf_bob = TestModule::allowlist_leaf(&bob, 2);
+            let leaf_charlie = TestModule::allowlist_leaf(&charlie, 1);
+            let root = if leaf_bob.as_ref() <= leaf_charlie.as_ref() {
+                BlakeTwo256::hash_of(&(leaf_bob, leaf_charlie))
+            } else {
+                BlakeTwo256::hash_of(&(leaf_charlie, leaf_bob))
+            };
+
+            assert_noop!(TestModule::set_allowlist(Origin::signed(bob), Some(root), b"ipfs://drop".to_vec()),
+                "Only issuer can manage allowlist");
+            assert_ok!(TestModule::set_allowlist(Origin::signed(alice), Some(root), b"ipfs://drop".to_vec()));
+
+            assert_noop!(TestModule::allowlist_mint(Origin::signed(bob), alice, 3, vec![leaf_charlie]), "Invalid allowlist proof");
+            assert_noop!(TestModule::allowlist_mint(Origin::signed(dave), alice, 2, vec![leaf_charlie]), "Invalid allowlist proof");
+
+            assert_ok!(TestModule::allowlist_mint(Origin::signed(bob), alice, 2, vec![leaf_charlie]));
+            assert_ok!(TestModule::allowlist_mint(Origin::signed(bob), alice, 2, vec![leaf_charlie]));
+            assert_noop!(TestModule::allowlist_mint(Origin::signed(bob), alice, 2, vec![leaf_charlie]), "Allowlist quota used up");
+
+            // Another issuer's drop leaves alice's alone, even with the same root
+            assert_ok!(TestModule::set_issuer(Origin::ROOT, eve, true));
+            assert_ok!(TestModule::set_allowlist(Origin::signed(eve), Some(root), b"ipfs://other".to_vec()));
+            assert_eq!(TestModule::allowlist(alice).map(|drop| drop.uri), Some(b"ipfs://drop".to_vec()));
+            assert_ok!(TestModule::allowlist_mint(Origin::signed(bob), eve, 2, vec![leaf_charlie]));
+            assert_eq!(TestModule::issuer_of(2), Some(eve));
+            assert_ok!(TestModule::set_allowlist(Origin::signed(eve), None, Vec::new()));
+            assert_noop!(TestModule::allowlist_mint(Origin::signed(bob), eve, 2, vec![leaf_charlie]), "No allowlist drop is open");
+
+            assert_ok!(TestModule::allowlist_mint(Origin::signed(charlie), alice, 1, vec![leaf_bob]));
+
+            assert_eq!(TestModule::balance_of(&bob), 3);
+            assert_eq!(TestModule::balance_of(&charlie), 1);
+            assert_eq!(TestModule::token_uri(3), b"ipfs://drop".to_vec());
+        });
+    }
+    #[test]
+    fn test_primary_sale() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let dave = 3;
+            let eve = 4;
+            System::set_block_number(1);
+            let _ = Balances::deposit_creating(&bob, 100);
+            let _ = Balances::deposit_creating(&charlie, 100);
+
+            assert_noop!(TestModule::mint(Origin::signed(bob), alice), "No sale is configured");
+            assert_noop!(TestModule::set_sale(Origin::signed(bob), dave, 10, 5, 10, 2, 3, b"ipfs://sale".to_vec()),
+                "Only issuer can manage sale");
+            assert_ok!(TestModule::set_sale(Origin::signed(alice), dave, 10, 5, 10, 2, 3, b"ipfs://sale".to_vec()));
+            assert_noop!(TestModule::mint(Origin::signed(bob), alice), "Sale has not started");
+
+            // Another issuer's sale leaves alice's alone
+            assert_ok!(TestModule::set_issuer(Origin::ROOT, eve, true));
+            assert_ok!(TestModule::set_sale(Origin::signed(eve), eve, 50, 1, 2, 1, 1, b"ipfs://other".to_vec()));
+            assert_noop!(TestModule::close_sale(Origin::signed(dave)), "No sale is configured");
+            assert_eq!(TestModule::sale(alice).map(|sale| sale.price), Some(10));
+
+            System::set_block_number(5);
+            // A payment that can't reach the beneficiary mints nothing
+            assert_noop!(TestModule::mint(Origin::signed(bob), alice), "Payee account does not exist");
+            assert_eq!(Balances::reserved_balance(&bob), 0);
+            let _ = Balances::deposit_creating(&dave, 1);
+            assert_ok!(TestModule::mint(Origin::signed(bob), alice));
+            assert_ok!(TestModule::mint(Origin::signed(bob), alice));
+            assert_noop!(TestModule::mint(Origin::signed(bob), alice), "Sale per-account cap reached");
+            assert_ok!(TestModule::mint(Origin::signed(charlie), alice));
+            assert_noop!(TestModule::mint(Origin::signed(charlie), alice), "Sale is sold out");
+
+            assert_eq!(Balances::free_balance(&dave), 31);
+            assert_eq!(Balances::free_balance(&bob), 80);
+            assert_eq!(TestModule::balance_of(&bob), 2);
+
+            System::set_block_number(11);
+            assert_ok!(TestModule::set_sale(Origin::signed(alice), dave, 10, 5, 10, 2, 3, b"ipfs://sale".to_vec()));
+            assert_noop!(TestModule::mint(Origin::signed(bob), alice), "Sale has ended");
+            assert_ok!(TestModule::close_sale(Origin::signed(eve)));
+            assert_eq!(TestModule::sale(eve), None);
+            assert!(TestModule::sale(alice).is_some());
+        });
+    }
+    #[test]
+    fn test_commit_reveal() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let uris = vec![b"ipfs://a".to_vec(), b"ipfs://b".to_vec(), b"ipfs://c".to_vec()];
+            let commitment = TestModule::reveal_commitment(&uris, b"salt");
+
+            assert_noop!(TestModule::issue_hidden(Origin::signed(alice), bob, 5, b"ipfs://hidden".to_vec(), commitment, false),
+                "Batch is too large");
+            assert_ok!(TestModule::set_max_supply(Origin::ROOT, Some(8)));
+            assert_noop!(TestModule::issue_hidden(Origin::signed(alice), 99, 3, b"ipfs://hidden".to_vec(), commitment, false),
+                "Account 99 does not accept tokens");
+            assert_ok!(TestModule::issue_hidden(Origin::signed(alice), bob, 3, b"ipfs://hidden".to_vec(), commitment, false));
+            assert_ok!(TestModule::issue_hidden(Origin::signed(alice), bob, 3, b"ipfs://hidden".to_vec(), commitment, true));
+            assert_eq!(TestModule::balance_of(&bob), 6);
+            assert_noop!(TestModule::issue_hidden(Origin::signed(alice), bob, 3, b"ipfs://hidden".to_vec(), commitment, false),
+                "Token supply cap reached");
+            assert!(TestModule::hidden_batch(1).unwrap().shuffle_seed.is_some());
+            assert_eq!(TestModule::token_uri(4), b"ipfs://hidden".to_vec());
+
+            assert_noop!(TestModule::reveal(Origin::signed(bob), 0, uris.clone(), b"salt".to_vec()),
+                "Only the issuer can reveal the batch");
+            assert_noop!(TestModule::reveal(Origin::signed(alice), 0, uris.clone(), b"pepper".to_vec()),
+                "URIs do not match commitment");
+
+            assert_ok!(TestModule::reveal(Origin::signed(alice), 0, uris.clone(), b"salt".to_vec()));
+            assert_eq!((0u128..3).map(TestModule::token_uri).collect::<Vec<_>>(), uris);
+
+            assert_ok!(TestModule::reveal(Origin::signed(alice), 1, uris.clone(), b"salt".to_vec()));
+            let mut revealed = (3u128..6).map(TestModule::token_uri).collect::<Vec<_>>();
+            revealed.sort();
+            assert_eq!(revealed, uris);
+            assert_eq!(TestModule::hidden_batch(1), None);
+        });
+    }
+    #[test]
+    fn test_tickets() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let dave = 3;
+            let _ = Balances::deposit_creating(&bob, 1);
+            let _ = Balances::deposit_creating(&charlie, 100);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]));
+
+            assert_noop!(TestModule::set_resale_cap(Origin::signed(bob), 0, Some(50)), "Only the issuer can set resale cap");
+            assert_ok!(TestModule::set_resale_cap(Origin::signed(alice), 0, Some(50)));
+            assert_noop!(TestModule::ask(Origin::signed(bob), 0, Some(60)), "Price exceeds resale cap");
+            assert_ok!(TestModule::ask(Origin::signed(bob), 0, Some(40)));
+            assert_ok!(TestModule::set_resale_cap(Origin::signed(alice), 0, Some(30)));
+            assert_noop!(TestModule::buy(Origin::signed(charlie), 0, 40), "Price exceeds resale cap");
+            assert_ok!(TestModule::ask(Origin::signed(bob), 0, Some(30)));
+            assert_ok!(TestModule::buy(Origin::signed(charlie), 0, 30));
+            assert_eq!(TestModule::owner_of(0), Some(charlie));
+            assert_eq!(Balances::free_balance(&bob), 31);
+
+            assert_noop!(TestModule::redeem_ticket(Origin::signed(dave), 0), "Only verifier can redeem ticket");
+            // A verifier appointed by another issuer can't check in alice's tickets
+            assert_ok!(TestModule::set_verifier(Origin::signed(bob), dave, true));
+            assert_noop!(TestModule::redeem_ticket(Origin::signed(dave), 0), "Only verifier can redeem ticket");
+            assert_ok!(TestModule::set_verifier(Origin::signed(alice), dave, true));
+            assert_ok!(TestModule::redeem_ticket(Origin::signed(dave), 0));
+            assert!(TestModule::is_redeemed(0));
+            assert_noop!(<TestModule as NFTS<_, _>>::transfer_from(charlie, bob, 0, vec![]), "Ticket has been redeemed");
+        });
+    }
+    #[test]
+    fn test_expiring_tokens() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            System::set_block_number(1);
+            for _ in 0..3 {
+                assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            }
+            assert_noop!(TestModule::set_expiry(Origin::signed(bob), 0, Some(5)), "Only the issuer can set expiry");
+            for token_id in 0..2 {
+                assert_ok!(TestModule::set_expiry(Origin::signed(alice), token_id, Some(5)));
+            }
+            // One unit of the sweep budget goes to visiting the block
+            assert_noop!(TestModule::set_expiry(Origin::signed(alice), 2, Some(5)), "Too many tokens expire in this block");
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 2, Some(6)));
+            // Rescheduling moves the token instead of queueing it twice
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 2, Some(7)));
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 2, Some(7)));
+            assert_eq!(TestModule::expiry_queue(6), Vec::<u128>::new());
+            assert_eq!(TestModule::expiry_queue(7), vec![2]);
+
+            // Once sold, the issuer can only push the expiry back
+            for token_id in 0..3 {
+                assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, token_id, vec![]));
+            }
+            assert_noop!(TestModule::set_expiry(Origin::signed(alice), 2, Some(6)), "Expiry can only be extended once the token is sold or rented");
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 2, Some(7)));
+
+            assert_noop!(TestModule::extend_expiry(Origin::signed(bob), 2, 8), "Issuer does not allow extensions");
+            assert_ok!(TestModule::set_extension_policy(Origin::signed(alice), Some(10)));
+            assert_noop!(TestModule::extend_expiry(Origin::signed(bob), 2, 12), "Extension exceeds issuer policy");
+            assert_ok!(TestModule::extend_expiry(Origin::signed(bob), 2, 8));
+            assert_eq!(TestModule::expiry_queue(8), vec![2]);
+
+            System::set_block_number(5);
+            assert!(!TestModule::is_valid(0));
+            assert!(TestModule::is_valid(2));
+            // Expired tokens have no owner even before they are swept
+            assert_eq!(TestModule::owner_of(0), None);
+            TestModule::on_initialize(5);
+            assert_eq!(TestModule::total_supply(), 1);
+            assert_eq!(TestModule::balance_of(&bob), 1);
+            assert_eq!(TestModule::owner_of(2), Some(bob));
+
+            // Burned ids are not reused
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_eq!(TestModule::owner_of(3), Some(alice));
+
+            // Tokens nested in an expiring token are handed to its owner first
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::nest(Origin::signed(alice), 4, 3));
+            assert_ok!(TestModule::set_expiry(Origin::signed(alice), 3, Some(6)));
+            System::set_block_number(6);
+            TestModule::on_initialize(6);
+            assert_eq!(TestModule::token_owner(3), None);
+            assert_eq!(TestModule::token_owner(4), Some(Ownership::Account(alice)));
+            assert_eq!(TestModule::children_of(3), Vec::<u128>::new());
+            assert_eq!(TestModule::expiry_queue(7), Vec::<u128>::new());
+
+            for block in 7..9 {
+                System::set_block_number(block);
+                TestModule::on_initialize(block);
+            }
+            assert_eq!(TestModule::token_owner(2), None);
+            assert_eq!(TestModule::total_supply(), 1);
+            assert_eq!(TestModule::balance_of(&bob), 0);
+        });
+    }
+    #[test]
+    fn test_controlled_attributes() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let game = 2;
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]));
+
+            assert_noop!(TestModule::set_attribute(Origin::signed(bob), 0, b"name".to_vec(), Some(b"Excalibur".to_vec())),
+                "Attribute value too long");
+            assert_noop!(TestModule::set_attribute(Origin::signed(bob), 0, b"nickname".to_vec(), Some(b"Sting".to_vec())),
+                "Attribute key too long");
+            assert_ok!(TestModule::set_attribute(Origin::signed(bob), 0, b"name".to_vec(), Some(b"Sting".to_vec())));
+            assert_ok!(TestModule::set_attribute(Origin::signed(bob), 0, b"owner".to_vec(), Some(b"Bilbo".to_vec())));
+            assert_noop!(TestModule::set_attribute(Origin::signed(bob), 0, b"maker".to_vec(), Some(b"Elves".to_vec())),
+                "Token has too many attributes");
+            // Overwriting or clearing an attribute doesn't need a free slot
+            assert_ok!(TestModule::set_attribute(Origin::signed(bob), 0, b"owner".to_vec(), Some(b"Frodo".to_vec())));
+            assert_ok!(TestModule::set_attribute(Origin::signed(bob), 0, b"owner".to_vec(), None));
+            assert_ok!(TestModule::set_attribute(Origin::signed(bob), 0, b"maker".to_vec(), Some(b"Elves".to_vec())));
+            assert_eq!(TestModule::attribute_keys(0), vec![b"name".to_vec(), b"maker".to_vec()]);
+            assert_noop!(TestModule::set_attribute(Origin::signed(game), 0, b"name".to_vec(), None), "Only owner can set attribute");
+            assert_noop!(TestModule::set_controlled_attribute(Origin::signed(bob), 0, b"level".to_vec(), Some(vec![99])),
+                "Only controller can set reserved attribute");
+
+            assert_ok!(TestModule::set_controller(Origin::signed(alice), game, true));
+            assert_ok!(TestModule::set_controlled_attribute(Origin::signed(game), 0, b"level".to_vec(), Some(vec![2])));
+            assert_eq!(TestModule::controlled_attribute((0, b"level".to_vec())), Some(vec![2]));
+            assert_eq!(TestModule::attribute((0, b"level".to_vec())), None);
+            assert_eq!(TestModule::attribute((0, b"name".to_vec())), Some(b"Sting".to_vec()));
+
+            // Burning a token removes its attributes
+            assert_ok!(<TestModule as NFTS<_, _>>::_burn(0));
+            assert_eq!(TestModule::attribute((0, b"name".to_vec())), None);
+            assert_eq!(TestModule::controlled_attribute((0, b"level".to_vec())), None);
+            assert_eq!(TestModule::attribute_keys(0), Vec::<Vec<u8>>::new());
+            assert_eq!(TestModule::controlled_attribute_keys(0), Vec::<Vec<u8>>::new());
+        });
+    }
+    #[test]
+    fn test_ownership_checkpoints() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            System::set_block_number(2);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            System::set_block_number(5);
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]));
+            System::set_block_number(8);
+            assert_ok!(<TestModule as NFTS<_, _>>::_burn(0));
+
+            assert_eq!(TestModule::balance_of_at(&alice, 1), 0);
+            assert_eq!(TestModule::balance_of_at(&alice, 2), 2);
+            assert_eq!(TestModule::balance_of_at(&alice, 4), 2);
+            assert_eq!(TestModule::balance_of_at(&alice, 5), 1);
+            assert_eq!(TestModule::balance_of_at(&bob, 7), 1);
+            assert_eq!(TestModule::balance_of_at(&bob, 100), 0);
+
+            assert_eq!(TestModule::owner_of_at(0, 1), None);
+            assert_eq!(TestModule::owner_of_at(0, 3), Some(alice));
+            assert_eq!(TestModule::owner_of_at(0, 6), Some(bob));
+            assert_eq!(TestModule::owner_of_at(0, 8), None);
+            assert_eq!(TestModule::owner_of_at(1, 100), Some(alice));
+        });
+    }
+    #[test]
+    fn test_transfer_hook() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            System::set_block_number(1);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            assert_ok!(TestModule::nest(Origin::signed(alice), 1, 0));
+
+            assert_noop!(<TestModule as NFTS<_, _>>::transfer_from(alice, 99, 0, vec![]), "Account 99 does not accept tokens");
+            assert_noop!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![0; 9]), "Transfer data too long");
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, b"order-42".to_vec()));
+            assert_noop!(TestModule::burn(Origin::signed(alice), 1), "Only owner or approved account can burn token");
+            assert_ok!(TestModule::burn(Origin::signed(bob), 1));
+
+            assert_eq!(MOVES.with(|moves| moves.borrow().clone()), vec![
+                (None, Some(alice), 0, vec![]),
+                (None, Some(alice), 1, vec![]),
+                (Some(alice), Some(bob), 0, b"order-42".to_vec()),
+                (Some(alice), Some(bob), 1, b"order-42".to_vec()),
+                (Some(bob), None, 1, vec![]),
+            ]);
+
+            // Only the transfer carrying data is indexed
+            for topic in &[alice, bob] {
+                assert_eq!(System::event_topics(&(), &BlakeTwo256::hash_of(topic)).len(), 1);
+            }
+            assert_eq!(System::event_topics(&(), &BlakeTwo256::hash_of(&0u128)).len(), 1);
+            assert!(System::event_topics(&(), &BlakeTwo256::hash_of(&1u128)).is_empty());
+
+            // A sale the hook refuses takes no payment
+            let _ = Balances::deposit_creating(&bob, 1);
+            let _ = Balances::deposit_creating(&99, 100);
+            assert_ok!(TestModule::ask(Origin::signed(bob), 0, Some(10)));
+            assert_noop!(TestModule::buy(Origin::signed(99), 0, 10), "Account 99 does not accept tokens");
+            assert_eq!(Balances::free_balance(&99), 100);
+            assert_eq!(Balances::reserved_balance(&99), 0);
+            assert_eq!(Balances::free_balance(&bob), 1);
+        });
+    }
+    #[test]
+    fn test_provenance() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            let charlie = 2;
+            let _ = Balances::deposit_creating(&bob, 1);
+            let _ = Balances::deposit_creating(&charlie, 100);
+            let record = |from, to, block, price| ProvenanceRecord { from, to, block, price };
+
+            System::set_block_number(1);
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"https://this_is_a_test.com".to_vec()));
+            System::set_block_number(2);
+            assert_ok!(<TestModule as NFTS<_, _>>::transfer_from(alice, bob, 0, vec![]));
+            assert_eq!(TestModule::provenance_of(0), vec![
+                record(None, Some(alice), 1, None),
+                record(Some(alice), Some(bob), 2, None),
+            ]);
+
+            System::set_block_number(3);
+            assert_ok!(TestModule::ask(Origin::signed(bob), 0, Some(40)));
+            assert_ok!(TestModule::buy(Origin::signed(charlie), 0, 40));
+            System::set_block_number(4);
+            assert_ok!(TestModule::burn(Origin::signed(charlie), 0));
+
+            // Only the latest records are kept, and they outlive the token
+            assert_eq!(TestModule::provenance_of(0), vec![
+                record(Some(alice), Some(bob), 2, None),
+                record(Some(bob), Some(charlie), 3, Some(40)),
+                record(Some(charlie), None, 4, None),
+            ]);
+        });
+    }
+    #[test]
+    fn test_instances_are_independent() {
+        with_externalities(&mut new_test_ext(), || {
+            let alice = 0;
+            let bob = 1;
+            <Issuers<Test, Instance1>>::insert(alice, true);
+
+            assert_ok!(TestModule::issue_with_uri(Origin::signed(alice), b"ipfs://art".to_vec()));
+            assert_ok!(TicketModule::issue_with_uri(Origin::signed(alice), b"ipfs://ticket".to_vec()));
+            assert_ok!(TicketModule::issue_with_uri(Origin::signed(alice), b"ipfs://ticket".to_vec()));
+            assert_eq!(TestModule::total_supply(), 1);
+            assert_eq!(TicketModule::total_supply(), 2);
+
+            let ticket = U256::from(1);
+            assert_eq!(TicketModule::next_token_id(), U256::from(2));
+            assert_ok!(<TicketModule as NFTS<u64, U256>>::transfer_from(alice, bob, ticket, vec![]));
+            assert_eq!(TicketModule::owner_of(ticket), Some(bob));
+            assert_eq!(TestModule::owner_of(1), None);
+            assert_eq!(TestModule::balance_of(&bob), 0);
+        });
+    }
+    #[test]
+    fn test_token_id_advance() {
+        assert_eq!(u32::max_value().checked_advance(1), None);
+        assert_eq!(U256::max_value().checked_advance(1), None);
+        assert_eq!(U256::from(7).checked_advance(3), Some(U256::from(10)));
+
+        // Hash ids carry across bytes like big-endian numbers
+        let mut bytes = [0u8; 32];
+        bytes[31] = 0xff;
+        let mut next = [0u8; 32];
+        next[30] = 1;
+        assert_eq!(H256::from(bytes).checked_advance(1), Some(H256::from(next)));
+        assert_eq!(H256::repeat_byte(0xff).checked_advance(1), None);
+    }
+}
@@ -0,0 +1,296 @@
+use support::{
+	decl_module, decl_storage, decl_event, ensure, StorageValue, StorageMap,
+	Parameter, dispatch::Dispatchable, traits::{Get, Currency, ReservableCurrency, EnsureOrigin},
+};
+use sr_primitives::traits::{Zero, One, CheckedAdd, Saturating};
+use sr_primitives::Perbill;
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use rstd::{prelude::*, marker::PhantomData, result};
+use crate::nfts;
+
+/// Voting power is the number of `nfts` tokens held, so the NFT registry must be part of the runtime.
+pub trait Trait: system::Trait + nfts::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// The outer origin, which must be able to carry this module's origin.
+	type Origin: From<RawOrigin>;
+	/// The call dispatched when a proposal passes. It is dispatched with `RawOrigin::TokenHolders`.
+	type Proposal: Parameter + Dispatchable<Origin = <Self as Trait>::Origin>;
+	/// How many blocks a proposal is open for voting.
+	type VotingPeriod: Get<Self::BlockNumber>;
+	type Currency: ReservableCurrency<Self::AccountId>;
+	/// Reserved from the proposer until the proposal is tallied.
+	type ProposalDeposit: Get<BalanceOf<Self>>;
+	/// The share of the total supply at the snapshot that must vote for a proposal to pass.
+	type Quorum: Get<Perbill>;
+	/// How many proposals may be tallied in one block.
+	type MaxProposalsPerBlock: Get<u32>;
+}
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+pub type ProposalIndex = u32;
+
+/// The origin of calls approved by token holders.
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum RawOrigin {
+	/// A proposal passed a token holder vote.
+	TokenHolders,
+}
+
+pub type Origin = RawOrigin;
+
+/// Accepts root, or a call approved by token holders. Use it for the admin origin of
+/// modules that token holders should govern.
+pub struct EnsureRootOrTokenHolders<AccountId>(PhantomData<AccountId>);
+
+impl<O, AccountId> EnsureOrigin<O> for EnsureRootOrTokenHolders<AccountId> where
+	O: Into<result::Result<system::RawOrigin<AccountId>, O>> + From<system::RawOrigin<AccountId>>
+		+ Into<result::Result<RawOrigin, O>> + From<RawOrigin>,
+{
+	type Success = ();
+	fn try_origin(o: O) -> result::Result<(), O> {
+		match Into::<result::Result<system::RawOrigin<AccountId>, O>>::into(o) {
+			Ok(system::RawOrigin::Root) => Ok(()),
+			Ok(other) => Err(O::from(other)),
+			Err(o) => Into::<result::Result<RawOrigin, O>>::into(o).map(|RawOrigin::TokenHolders| ()),
+		}
+	}
+}
+
+#[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq))]
+#[derive(Encode, Decode, Clone)]
+pub struct ProposalInfo<AccountId, BlockNumber, Votes, Balance> {
+	pub proposer: AccountId,
+	/// Voting power is the token balance at the end of this block.
+	pub snapshot: BlockNumber,
+	/// Voting closes and the proposal is tallied at this block.
+	pub end: BlockNumber,
+	pub ayes: Votes,
+	pub nays: Votes,
+	/// Reserved from the proposer, returned when the proposal is tallied.
+	pub deposit: Balance,
+}
+
+type ProposalInfoOf<T> = ProposalInfo<
+	<T as system::Trait>::AccountId,
+	<T as system::Trait>::BlockNumber,
+	<T as nfts::Trait>::TokenCount,
+	BalanceOf<T>,
+>;
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Voting {
+		/// The number of proposals made so far. i.e. the next proposal index
+		pub ProposalCount get(proposal_count): ProposalIndex;
+		/// Open proposals
+		pub Proposals get(proposal): map ProposalIndex => Option<ProposalInfoOf<T>>;
+		/// The call of each open proposal
+		pub ProposalCalls get(proposal_call): map ProposalIndex => Option<T::Proposal>;
+		/// Proposals to tally at a given block, at most `MaxProposalsPerBlock`
+		pub ProposalsEndingAt get(proposals_ending_at): map T::BlockNumber => Vec<ProposalIndex>;
+		/// Accounts which voted on a proposal
+		pub HasVoted get(has_voted): map (ProposalIndex, T::AccountId) => bool;
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where
+		<T as system::Trait>::AccountId,
+		<T as system::Trait>::BlockNumber,
+		Votes = <T as nfts::Trait>::TokenCount,
+	{
+		/// A proposal is made. (proposer, proposal_index, end)
+		Proposed(AccountId, ProposalIndex, BlockNumber),
+		/// A vote is cast. (voter, proposal_index, approve, votes)
+		Voted(AccountId, ProposalIndex, bool, Votes),
+		/// A proposal passed and was dispatched. (proposal_index, dispatch succeeded)
+		Executed(ProposalIndex, bool),
+		/// A proposal was rejected or missed the quorum. (proposal_index)
+		Rejected(ProposalIndex),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin {
+		fn deposit_event() = default;
+
+		fn on_initialize(now: T::BlockNumber) {
+			for index in <ProposalsEndingAt<T>>::take(now) {
+				Self::tally(index);
+			}
+		}
+
+		/// Propose a call to dispatch with the token holders origin if token holders approve it.
+		/// Reserves `ProposalDeposit` until the proposal is tallied.
+		pub fn propose(origin, proposal: Box<T::Proposal>) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(!<nfts::Module<T>>::balance_of(&sender).is_zero(), "Only token holders can propose");
+
+			let index = Self::proposal_count();
+			let now = <system::Module<T>>::block_number();
+			let end = now.checked_add(&T::VotingPeriod::get()).ok_or("Voting period overflow")?;
+			let ending = Self::proposals_ending_at(end);
+			ensure!((ending.len() as u32) < T::MaxProposalsPerBlock::get(), "Too many proposals in this block");
+
+			let deposit = T::ProposalDeposit::get();
+			<T as Trait>::Currency::reserve(&sender, deposit)?;
+
+			<ProposalCount>::put(index + 1);
+			// Snapshot the block before, so tokens moved in this block can't vote twice
+			<Proposals<T>>::insert(index, ProposalInfo {
+				proposer: sender.clone(),
+				snapshot: now.saturating_sub(One::one()),
+				end,
+				ayes: Zero::zero(),
+				nays: Zero::zero(),
+				deposit,
+			});
+			<ProposalCalls<T>>::insert(index, *proposal);
+			<ProposalsEndingAt<T>>::mutate(end, |proposals| proposals.push(index));
+
+			Self::deposit_event(RawEvent::Proposed(sender, index, end));
+		}
+
+		/// Vote on an open proposal with all tokens held at the proposal's snapshot.
+		pub fn vote(origin, index: ProposalIndex, approve: bool) {
+			let sender = ensure_signed(origin)?;
+
+			let mut proposal = Self::proposal(index).ok_or("Proposal does not exist")?;
+			ensure!(<system::Module<T>>::block_number() < proposal.end, "Voting has closed");
+			ensure!(!Self::has_voted((index, sender.clone())), "Already voted");
+
+			let votes = <nfts::Module<T>>::balance_of_at(&sender, proposal.snapshot);
+			ensure!(!votes.is_zero(), "No voting power at snapshot");
+
+			if approve {
+				proposal.ayes = proposal.ayes.checked_add(&votes).ok_or("Vote overflow")?;
+			} else {
+				proposal.nays = proposal.nays.checked_add(&votes).ok_or("Vote overflow")?;
+			}
+			<Proposals<T>>::insert(index, proposal);
+			<HasVoted<T>>::insert((index, sender.clone()), true);
+
+			Self::deposit_event(RawEvent::Voted(sender, index, approve, votes));
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Close a proposal, dispatching its call if the quorum is met and the ayes have it.
+	fn tally(index: ProposalIndex) {
+		let proposal = match <Proposals<T>>::take(index) {
+			Some(proposal) => proposal,
+			None => return,
+		};
+		let call = <ProposalCalls<T>>::take(index);
+
+		<T as Trait>::Currency::unreserve(&proposal.proposer, proposal.deposit);
+
+		let turnout = proposal.ayes.saturating_add(proposal.nays);
+		let quorum = T::Quorum::get() * <nfts::Module<T>>::total_supply_at(proposal.snapshot);
+		match call {
+			Some(call) if turnout >= quorum && proposal.ayes > proposal.nays => {
+				let ok = call.dispatch(RawOrigin::TokenHolders.into()).is_ok();
+				Self::deposit_event(RawEvent::Executed(index, ok));
+			},
+			_ => Self::deposit_event(RawEvent::Rejected(index)),
+		}
+	}
+}
+
+/// Tests for Voting module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use support::{assert_ok, assert_noop};
+	use sr_primitives::traits::OnInitialize;
+	use crate::mock::*;
+	use crate::mock::Origin;
+	use crate::nfts::NFTS;
+
+	type VotingModule = Module<Test>;
+
+	fn mint(to: u64) {
+		assert_ok!(NFTs::set_minter(Origin::ROOT, to, true));
+		assert_ok!(NFTs::issue_with_uri(Origin::signed(to), b"https://this_is_a_test.com".to_vec()));
+	}
+
+	#[test]
+	fn approved_proposal_is_dispatched() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			let charlie = 3;
+			System::set_block_number(1);
+			for _ in 0..3 {
+				mint(alice);
+			}
+			mint(bob);
+			Balances::make_free_balance_be(&bob, 10);
+
+			System::set_block_number(2);
+			let proposal = Box::new(nfts::Call::set_max_supply(Some(10)));
+			assert_noop!(VotingModule::propose(Origin::signed(charlie), proposal.clone()), "Only token holders can propose");
+			assert_ok!(VotingModule::propose(Origin::signed(bob), proposal));
+			assert_eq!(Balances::reserved_balance(&bob), 5);
+
+			assert_ok!(VotingModule::vote(Origin::signed(bob), 0, false));
+			assert_noop!(VotingModule::vote(Origin::signed(bob), 0, false), "Already voted");
+
+			// Tokens moved after the snapshot carry no voting power
+			assert_ok!(<NFTs as NFTS<_, _>>::transfer_from(bob, charlie, 3, vec![]));
+			assert_noop!(VotingModule::vote(Origin::signed(charlie), 0, false), "No voting power at snapshot");
+
+			assert_ok!(VotingModule::vote(Origin::signed(alice), 0, true));
+			assert_eq!(VotingModule::proposal(0).map(|p| (p.ayes, p.nays)), Some((3, 1)));
+
+			System::set_block_number(12);
+			assert_noop!(VotingModule::vote(Origin::signed(alice), 0, true), "Voting has closed");
+			VotingModule::on_initialize(12);
+			assert_eq!(VotingModule::proposal(0), None);
+			assert_eq!(NFTs::max_supply(), Some(10));
+			assert_eq!(Balances::reserved_balance(&bob), 0);
+		});
+	}
+
+	#[test]
+	fn proposals_need_quorum_and_are_capped() {
+		with_externalities(&mut new_test_ext(), || {
+			let alice = 1;
+			let bob = 2;
+			System::set_block_number(1);
+			for _ in 0..3 {
+				mint(alice);
+			}
+			mint(bob);
+			Balances::make_free_balance_be(&alice, 5);
+			Balances::make_free_balance_be(&bob, 10);
+
+			// Passed proposals don't get root
+			assert!(NFTs::set_max_supply(Origin::signed(alice), Some(20)).is_err());
+
+			System::set_block_number(2);
+			let proposal = Box::new(nfts::Call::set_max_supply(Some(20)));
+			assert_ok!(VotingModule::propose(Origin::signed(bob), proposal.clone()));
+			assert_ok!(VotingModule::propose(Origin::signed(alice), Box::new(nfts::Call::set_issuer(bob, true))));
+			assert_noop!(VotingModule::propose(Origin::signed(bob), proposal), "Too many proposals in this block");
+
+			// One of four tokens is short of the 50% quorum
+			assert_ok!(VotingModule::vote(Origin::signed(bob), 0, true));
+			assert_ok!(VotingModule::vote(Origin::signed(alice), 1, true));
+
+			System::set_block_number(12);
+			VotingModule::on_initialize(12);
+			assert_eq!(NFTs::max_supply(), None);
+			assert_eq!(VotingModule::proposal(0), None);
+			assert!(NFTs::is_issuer(&bob));
+			assert_eq!(Balances::reserved_balance(&bob), 0);
+			assert_eq!(Balances::free_balance(&alice), 5);
+		});
+	}
+}
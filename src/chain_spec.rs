@@ -1,7 +1,7 @@
 use primitives::{Pair, Public};
 use substrate_nfts_runtime::{
 	AccountId, BabeConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
-	SudoConfig, IndicesConfig, SystemConfig, WASM_BINARY, 
+	SudoConfig, IndicesConfig, SystemConfig, NFTsConfig, TicketNFTsConfig, WASM_BINARY, 
 };
 use babe_primitives::{AuthorityId as BabeId};
 use grandpa_primitives::{AuthorityId as GrandpaId};
@@ -66,6 +66,7 @@ impl Alternative {
 					get_from_seed::<AccountId>("Eve//stash"),
 					get_from_seed::<AccountId>("Ferdie//stash"),
 				],
+				sample_nfts(),
 				true),
 				vec![],
 				None,
@@ -95,6 +96,7 @@ impl Alternative {
 					get_from_seed::<AccountId>("Eve//stash"),
 					get_from_seed::<AccountId>("Ferdie//stash"),
 				],
+				sample_nfts(),
 				true),
 				vec![],
 				None,
@@ -114,9 +116,19 @@ impl Alternative {
 	}
 }
 
+/// Sample tokens for Alice and Bob as `(owner, uri)`
+fn sample_nfts() -> Vec<(AccountId, Vec<u8>)> {
+	vec![
+		(get_from_seed::<AccountId>("Alice"), b"https://example.com/nfts/0".to_vec()),
+		(get_from_seed::<AccountId>("Alice"), b"https://example.com/nfts/1".to_vec()),
+		(get_from_seed::<AccountId>("Bob"), b"https://example.com/nfts/2".to_vec()),
+	]
+}
+
 fn testnet_genesis(initial_authorities: Vec<(AccountId, AccountId, GrandpaId, BabeId)>,
 	root_key: AccountId, 
 	endowed_accounts: Vec<AccountId>,
+	initial_nfts: Vec<(AccountId, Vec<u8>)>,
 	_enable_println: bool) -> GenesisConfig {
 	GenesisConfig {
 		system: Some(SystemConfig {
@@ -131,7 +143,7 @@ fn testnet_genesis(initial_authorities: Vec<(AccountId, AccountId, GrandpaId, Ba
 			vesting: vec![],
 		}),
 		sudo: Some(SudoConfig {
-			key: root_key,
+			key: root_key.clone(),
 		}),
 		babe: Some(BabeConfig {
 			authorities: initial_authorities.iter().map(|x| (x.3.clone(), 1)).collect(),
@@ -139,5 +151,15 @@ fn testnet_genesis(initial_authorities: Vec<(AccountId, AccountId, GrandpaId, Ba
 		grandpa: Some(GrandpaConfig {
 			authorities: initial_authorities.iter().map(|x| (x.2.clone(), 1)).collect(),
 		}),
+		nfts: Some(NFTsConfig {
+			tokens: initial_nfts,
+			operators: vec![],
+			issuers: vec![root_key.clone()],
+		}),
+		nfts_Instance1: Some(TicketNFTsConfig {
+			tokens: vec![],
+			operators: vec![],
+			issuers: vec![root_key],
+		}),
 	}
 }